@@ -1,76 +1,92 @@
-use crate::config::Config;
+use crate::metrics::METRICS;
+use crate::settings::SharedSettings;
 use crate::tracker::RequestTracker;
 use tracing::{info, warn};
 
-pub fn spawn(tracker: RequestTracker, config: &Config) {
-    let token = match &config.discord_bot_token {
-        Some(t) => t.clone(),
-        None => {
-            info!("Discord alerts disabled (BRIDGE_ECHO_DISCORD_BOT_TOKEN not set)");
-            return;
-        }
-    };
+/// Start the alert loop. Alert settings are re-read on every pass, so
+/// enabling, disabling or retuning alerts only needs a config reload.
+pub fn spawn(tracker: RequestTracker, settings: SharedSettings) {
+    match alert_target(&settings) {
+        Ok((_, channel_id, thresholds)) => info!(
+            "Discord alerts enabled — thresholds: {:?} min, channel: {channel_id}",
+            thresholds
+        ),
+        Err(reason) => info!("Discord alerts disabled ({reason})"),
+    }
 
-    let channel_id = match &config.discord_alert_channel {
-        Some(c) => c.clone(),
-        None => {
-            info!("Discord alerts disabled (BRIDGE_ECHO_DISCORD_ALERT_CHANNEL not set)");
-            return;
-        }
-    };
+    tokio::spawn(alert_loop(tracker, settings));
+}
 
-    let thresholds = config.alert_thresholds_minutes.clone();
-    if thresholds.is_empty() {
-        info!("Discord alerts disabled (no thresholds configured)");
-        return;
+/// Token, channel and thresholds from the current settings, or why alerts
+/// are off.
+fn alert_target(settings: &SharedSettings) -> Result<(String, String, Vec<u64>), &'static str> {
+    let settings = settings.load();
+    let config = &settings.config;
+    let token = config
+        .discord_bot_token
+        .clone()
+        .ok_or("BRIDGE_ECHO_DISCORD_BOT_TOKEN not set")?;
+    let channel_id = config
+        .discord_alert_channel
+        .clone()
+        .ok_or("BRIDGE_ECHO_DISCORD_ALERT_CHANNEL not set")?;
+    if config.alert_thresholds_minutes.is_empty() {
+        return Err("no thresholds configured");
     }
+    Ok((token, channel_id, config.alert_thresholds_minutes.clone()))
+}
 
-    info!(
-        "Discord alerts enabled — thresholds: {:?} min, channel: {channel_id}",
-        thresholds
-    );
-
-    tokio::spawn(async move {
-        alert_loop(tracker, &token, &channel_id, &thresholds).await;
-    });
+/// Post `content` to a Discord channel as the bot.
+pub async fn post_discord(
+    client: &reqwest::Client,
+    token: &str,
+    channel_id: &str,
+    content: &str,
+) -> reqwest::Result<reqwest::Response> {
+    client
+        .post(format!(
+            "https://discord.com/api/v10/channels/{channel_id}/messages"
+        ))
+        .header("Authorization", format!("Bot {token}"))
+        .json(&serde_json::json!({ "content": content }))
+        .send()
+        .await
 }
 
-async fn alert_loop(tracker: RequestTracker, token: &str, channel_id: &str, thresholds: &[u64]) {
+async fn alert_loop(tracker: RequestTracker, settings: SharedSettings) {
     let client = reqwest::Client::new();
-    let url = format!("https://discord.com/api/v10/channels/{channel_id}/messages");
 
     loop {
         tokio::time::sleep(std::time::Duration::from_secs(30)).await;
 
+        let Ok((token, channel_id, thresholds)) = alert_target(&settings) else {
+            continue;
+        };
         let requests = tracker.active_requests_for_alerting().await;
 
         for (id, channel, message_preview, elapsed_secs, alerts_sent) in requests {
             let elapsed_min = elapsed_secs / 60;
 
-            for &threshold in thresholds {
+            for &threshold in &thresholds {
                 if elapsed_min >= threshold && !alerts_sent.contains(&threshold) {
                     let msg = format!(
                         "⚠️ **bridge-echo alert** — request #{id} on `{channel}` has been running for **{elapsed_min} min**\n> {message_preview}"
                     );
 
-                    let res = client
-                        .post(&url)
-                        .header("Authorization", format!("Bot {token}"))
-                        .json(&serde_json::json!({ "content": msg }))
-                        .send()
-                        .await;
-
-                    match res {
+                    match post_discord(&client, &token, &channel_id, &msg).await {
                         Ok(r) if r.status().is_success() => {
                             info!("Alert sent for request #{id} at {threshold}min threshold");
+                            METRICS.alerts.inc("sent");
                         }
                         Ok(r) => {
+                            METRICS.alerts.inc("http_error");
                             warn!(
                                 "Discord alert failed for request #{id}: HTTP {}",
                                 r.status()
                             );
                         }
                         Err(e) => {
+                            METRICS.alerts.inc("error");
                             warn!("Discord alert failed for request #{id}: {e}");
                         }
                     }
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use regex::RegexSet;
+use tracing::{info, warn};
+
+use crate::alerts;
+use crate::audit::AuditLog;
+use crate::metrics::METRICS;
+use crate::queue::{self, Queue};
+use crate::settings::{Settings, SharedSettings};
+use crate::store;
+use crate::tracker::{HeldRequest, Outcome, RequestTracker};
+
+/// Reply to a held request that an admin rejected.
+pub const REJECTED_RESPONSE: &str = "This message was held for review and rejected.";
+/// Reply to a held request nobody decided on in time.
+const EXPIRED_RESPONSE: &str = "This message was held for review and not approved in time.";
+
+/// Compile `approval.tool_patterns`.
+pub fn tool_patterns(patterns: &[String]) -> Result<RegexSet, String> {
+    for (i, pattern) in patterns.iter().enumerate() {
+        regex::Regex::new(pattern).map_err(|e| format!("approval.tool_patterns[{i}]: {e}"))?;
+    }
+    RegexSet::new(patterns).map_err(|e| format!("approval.tool_patterns: {e}"))
+}
+
+/// Release a held request into the queue. Returns false if it is not held.
+pub async fn approve(queue: &Queue, tracker: &RequestTracker, id: u64) -> bool {
+    // Clear the tracker first so a worker picking it up straight away does
+    // not show it as both held and active.
+    tracker.unhold(id).await;
+    if !queue.approve(id).await {
+        return false;
+    }
+    METRICS.approvals.inc("approved");
+    info!("Held request #{id} approved");
+    true
+}
+
+/// Answer a held request with `response` without running it. Returns false
+/// if it is not held.
+pub async fn reject(
+    queue: &Queue,
+    tracker: &RequestTracker,
+    audit: Option<&AuditLog>,
+    id: u64,
+    response: &str,
+) -> bool {
+    let Some(req) = queue.take_held(id).await else {
+        return false;
+    };
+    tracker.unhold(id).await;
+    queue::settle(req, response, Outcome::Rejected, tracker, audit).await;
+    true
+}
+
+/// Start the loop that rejects held requests once `approval.timeout` has
+/// passed without a decision.
+pub fn spawn(
+    queue: Queue,
+    tracker: RequestTracker,
+    settings: SharedSettings,
+    audit: Option<AuditLog>,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+
+            let timeout = settings.load().config.approval_timeout_secs;
+            let now = store::now_unix();
+            for held in tracker.held_snapshot().await {
+                if now.saturating_sub(held.held_unix) < timeout {
+                    continue;
+                }
+                let id = held.id;
+                if reject(&queue, &tracker, audit.as_ref(), id, EXPIRED_RESPONSE).await {
+                    METRICS.approvals.inc("expired");
+                    warn!("Held request #{id} expired without a decision");
+                }
+            }
+        }
+    });
+}
+
+/// Tell the approval Discord channel about a held request, if one is
+/// configured along with the bot token.
+pub async fn notify(settings: &Settings, held: &HeldRequest) {
+    let config = &settings.config;
+    let (Some(token), Some(channel_id)) =
+        (&config.discord_bot_token, &config.approval_discord_channel)
+    else {
+        return;
+    };
+
+    let id = held.id;
+    let msg = format!(
+        "🛑 **bridge-echo approval needed** — request #{id} on `{}` from `{}` is held ({})\n> {}\nApprove with `POST /admin/held/{id}/approve` or reject with `POST /admin/held/{id}/reject`",
+        held.channel, held.sender, held.reason, held.message_preview
+    );
+
+    match alerts::post_discord(&reqwest::Client::new(), token, channel_id, &msg).await {
+        Ok(r) if r.status().is_success() => {
+            info!("Approval notification sent for request #{id}");
+        }
+        Ok(r) => warn!(
+            "Approval notification failed for request #{id}: HTTP {}",
+            r.status()
+        ),
+        Err(e) => warn!("Approval notification failed for request #{id}: {e}"),
+    }
+}
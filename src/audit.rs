@@ -0,0 +1,375 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::injection::Detection;
+use crate::store;
+use crate::tracker::Outcome;
+use crate::trust::TrustLevel;
+
+/// Hash that precedes the first record ever written.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What happened to one request.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub request_id: u64,
+    pub channel: String,
+    pub sender: String,
+    pub trust: TrustLevel,
+    /// Injection patterns that matched the message, with their severity.
+    pub injection: Detection,
+    /// SHA-256 of the prompt sent to Claude, hex encoded.
+    pub prompt_sha256: String,
+    pub session_id: Option<String>,
+    pub outcome: Outcome,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub response: String,
+}
+
+/// One line of the audit log. `hash` covers `prev_hash`, `seq`, the
+/// timestamp and the entry, so removing or editing any line breaks the
+/// chain from that point on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Line {
+    seq: u64,
+    timestamp_unix: u64,
+    #[serde(flatten)]
+    entry: AuditEntry,
+    prev_hash: String,
+    hash: String,
+}
+
+impl Line {
+    fn compute_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(self.seq.to_string().as_bytes());
+        hasher.update(self.timestamp_unix.to_string().as_bytes());
+        hasher.update(serde_json::to_vec(&self.entry).unwrap_or_default());
+        hex(&hasher.finalize())
+    }
+}
+
+struct Tail {
+    file: Option<PathBuf>,
+    /// UTC day of the current file, as days since the epoch.
+    day: u64,
+    /// Suffix of the current file within its day.
+    part: u32,
+    size: u64,
+    next_seq: u64,
+    last_hash: String,
+}
+
+/// Append-only, hash-chained JSONL audit log with one record per request.
+/// Files are named `audit-YYYY-MM-DD-NNN.jsonl` and rotate at the start of
+/// each UTC day (if enabled) or once they reach `max_bytes`. The chain
+/// continues across files.
+#[derive(Clone)]
+pub struct AuditLog {
+    dir: PathBuf,
+    max_bytes: u64,
+    rotate_daily: bool,
+    tail: Arc<Mutex<Tail>>,
+}
+
+impl AuditLog {
+    /// Open the log in `dir`, continuing the chain from the newest record.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64, rotate_daily: bool) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut tail = Tail {
+            file: None,
+            day: 0,
+            part: 0,
+            size: 0,
+            next_seq: 0,
+            last_hash: GENESIS.into(),
+        };
+        if let Some(path) = audit_files(&dir)?.pop() {
+            if let Some(last) = read_lines(&path)?.pop() {
+                tail.next_seq = last.seq + 1;
+                tail.last_hash = last.hash;
+            }
+            if let Some((day, part)) = parse_name(&path) {
+                tail.day = day;
+                tail.part = part;
+                tail.size = fs::metadata(&path)?.len();
+                tail.file = Some(path);
+            }
+            info!(
+                "Audit log continues at record {} in {}",
+                tail.next_seq,
+                dir.display()
+            );
+        }
+
+        Ok(Self {
+            dir,
+            max_bytes,
+            rotate_daily,
+            tail: Arc::new(Mutex::new(tail)),
+        })
+    }
+
+    /// Append one record. Failures are logged; the chain only advances on a
+    /// successful write.
+    pub fn record(&self, entry: AuditEntry) {
+        let mut tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+        let now = store::now_unix();
+        let mut line = Line {
+            seq: tail.next_seq,
+            timestamp_unix: now,
+            entry,
+            prev_hash: tail.last_hash.clone(),
+            hash: String::new(),
+        };
+        line.hash = line.compute_hash();
+
+        let result = serde_json::to_string(&line)
+            .map_err(io::Error::from)
+            .and_then(|json| {
+                let path = self.file_for(&mut tail, now / 86_400, json.len() as u64 + 1);
+                let mut f = OpenOptions::new().create(true).append(true).open(&path)?;
+                writeln!(f, "{json}")?;
+                tail.size += json.len() as u64 + 1;
+                Ok(())
+            });
+
+        match result {
+            Ok(()) => {
+                tail.next_seq += 1;
+                tail.last_hash = line.hash;
+            }
+            Err(e) => warn!(
+                "failed to write audit record for request #{}: {e}",
+                line.entry.request_id
+            ),
+        }
+    }
+
+    /// The file the next record of `len` bytes goes to, rotating if needed.
+    fn file_for(&self, tail: &mut Tail, today: u64, len: u64) -> PathBuf {
+        let rotate = match &tail.file {
+            None => true,
+            Some(_) if self.rotate_daily && today != tail.day => true,
+            Some(_) => tail.size > 0 && tail.size + len > self.max_bytes,
+        };
+        if rotate {
+            if tail.file.is_some() && (!self.rotate_daily || today == tail.day) {
+                tail.part += 1;
+            } else {
+                tail.day = today;
+                tail.part = 0;
+            }
+            tail.size = 0;
+            tail.file = Some(self.dir.join(file_name(tail.day, tail.part)));
+        }
+        tail.file.clone().expect("set above")
+    }
+}
+
+/// Check every record in `dir`: hashes must match their contents and each
+/// record must point at the one before it. Returns the number of records.
+pub fn verify(dir: &Path) -> Result<u64, String> {
+    let mut expected_seq = 0;
+    let mut prev_hash = GENESIS.to_string();
+    for path in audit_files(dir).map_err(|e| format!("{}: {e}", dir.display()))? {
+        let lines = read_lines(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        for line in lines {
+            let at = format!("{} record {}", path.display(), line.seq);
+            if line.seq != expected_seq {
+                return Err(format!(
+                    "{at}: expected record {expected_seq}, records are missing"
+                ));
+            }
+            if line.prev_hash != prev_hash {
+                return Err(format!("{at}: does not follow the previous record"));
+            }
+            if line.compute_hash() != line.hash {
+                return Err(format!("{at}: contents do not match its hash"));
+            }
+            expected_seq += 1;
+            prev_hash = line.hash;
+        }
+    }
+    Ok(expected_seq)
+}
+
+/// Hex-encoded SHA-256 of `text`.
+pub fn sha256_hex(text: &str) -> String {
+    hex(&Sha256::digest(text.as_bytes()))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Audit files in chain order.
+fn audit_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter_map(|p| parse_name(&p).map(|key| (key, p)))
+        .collect();
+    files.sort();
+    Ok(files.into_iter().map(|(_, p)| p).collect())
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<Line>> {
+    let mut lines = Vec::new();
+    for (n, text) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let text = text?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let line = serde_json::from_str(&text).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {e}", n + 1))
+        })?;
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+fn file_name(day: u64, part: u32) -> String {
+    let (y, m, d) = civil_from_days(day);
+    format!("audit-{y:04}-{m:02}-{d:02}-{part:03}.jsonl")
+}
+
+/// Inverse of [`file_name`]: (day, part).
+fn parse_name(path: &Path) -> Option<(u64, u32)> {
+    let stem = path.file_name()?.to_str()?.strip_prefix("audit-")?;
+    let stem = stem.strip_suffix(".jsonl")?;
+    let mut parts = stem.split('-');
+    let y: i64 = parts.next()?.parse().ok()?;
+    let m: u32 = parts.next()?.parse().ok()?;
+    let d: u32 = parts.next()?.parse().ok()?;
+    let part: u32 = parts.next()?.parse().ok()?;
+    Some((days_from_civil(y, m, d)?, part))
+}
+
+/// Gregorian date of a day count since 1970-01-01.
+fn civil_from_days(days: u64) -> (i64, u32, u32) {
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+fn days_from_civil(y: i64, m: u32, d: u32) -> Option<u64> {
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = i64::from(m);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(d) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    u64::try_from(era * 146_097 + doe - 719_468).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("bridge-echo-audit-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn entry(id: u64) -> AuditEntry {
+        AuditEntry {
+            request_id: id,
+            channel: "discord".into(),
+            sender: "D".into(),
+            trust: TrustLevel::Verified,
+            injection: Detection::default(),
+            prompt_sha256: sha256_hex("prompt"),
+            session_id: Some("s-1".into()),
+            outcome: Outcome::Completed,
+            exit_code: Some(0),
+            duration_ms: 1200,
+            response: format!("response {id}"),
+        }
+    }
+
+    #[test]
+    fn dates_round_trip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(20_378), (2025, 10, 17));
+        assert_eq!(file_name(20_378, 2), "audit-2025-10-17-002.jsonl");
+        assert_eq!(
+            parse_name(Path::new("audit-2025-10-17-002.jsonl")),
+            Some((20_378, 2))
+        );
+    }
+
+    #[test]
+    fn chain_survives_rotation_and_reopen() {
+        let dir = temp_dir("rotate");
+        let log = AuditLog::open(&dir, 400, true).unwrap();
+        for id in 0..3 {
+            log.record(entry(id));
+        }
+        drop(log);
+        let log = AuditLog::open(&dir, 400, true).unwrap();
+        log.record(entry(3));
+
+        assert!(
+            audit_files(&dir).unwrap().len() > 1,
+            "expected size rotation"
+        );
+        assert_eq!(verify(&dir), Ok(4));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn deleted_record_is_detected() {
+        let dir = temp_dir("delete");
+        let log = AuditLog::open(&dir, u64::MAX, false).unwrap();
+        for id in 0..3 {
+            log.record(entry(id));
+        }
+        let path = audit_files(&dir).unwrap().pop().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let kept: Vec<_> = text.lines().enumerate().filter(|(i, _)| *i != 1).collect();
+        let rewritten: String = kept.iter().map(|(_, l)| format!("{l}\n")).collect();
+        fs::write(&path, rewritten).unwrap();
+
+        let err = verify(&dir).unwrap_err();
+        assert!(err.contains("missing"), "{err}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn edited_record_is_detected() {
+        let dir = temp_dir("edit");
+        let log = AuditLog::open(&dir, u64::MAX, false).unwrap();
+        log.record(entry(0));
+        let path = audit_files(&dir).unwrap().pop().unwrap();
+        let text = fs::read_to_string(&path)
+            .unwrap()
+            .replace("response 0", "something else");
+        fs::write(&path, text).unwrap();
+
+        let err = verify(&dir).unwrap_err();
+        assert!(err.contains("hash"), "{err}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
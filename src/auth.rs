@@ -0,0 +1,320 @@
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::state::AppState;
+use crate::trust::TrustLevel;
+
+/// A bearer token allowed to submit requests on a fixed set of channels.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiToken {
+    /// Label used in logs instead of the token itself.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub token: String,
+    /// Channels this token may use. The first is the default when a
+    /// request does not name one.
+    pub channels: Vec<String>,
+}
+
+impl ApiToken {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or("unnamed token")
+    }
+}
+
+/// Tokens for each group of endpoints. A group with no token configured is
+/// left open, as before authentication existed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuthConfig {
+    /// Tokens for `/chat`, `/chat/stream` and `/jobs`.
+    pub api_tokens: Vec<ApiToken>,
+    /// Token for `/api/status`, `/api/queue`, `/api/voice-sessions` and
+    /// `/metrics`.
+    pub status_token: Option<String>,
+    /// Token voice-echo uses for `/session-started` and `/call-ended`.
+    pub notify_token: Option<String>,
+    /// Token for `/admin/*`.
+    pub admin_token: Option<String>,
+}
+
+impl AuthConfig {
+    /// Parse `BRIDGE_ECHO_API_TOKENS`: `token:chan[,chan...]` entries
+    /// separated by `;`.
+    pub fn parse_api_tokens(value: &str) -> Result<Vec<ApiToken>, String> {
+        value
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .enumerate()
+            .map(|(i, entry)| {
+                let (token, channels) = entry
+                    .split_once(':')
+                    .ok_or_else(|| format!("entry {i}: expected token:channel[,channel...]"))?;
+                Ok(ApiToken {
+                    name: Some(format!("env token {i}")),
+                    token: token.trim().to_string(),
+                    channels: channels
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|c| !c.is_empty())
+                        .map(String::from)
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, t) in self.api_tokens.iter().enumerate() {
+            if t.token.is_empty() {
+                return Err(format!("auth.tokens[{i}]: token must not be empty"));
+            }
+            if t.channels.is_empty() {
+                return Err(format!("auth.tokens[{i}]: channels must not be empty"));
+            }
+        }
+        for (name, token) in [
+            ("auth.status_token", &self.status_token),
+            ("auth.notify_token", &self.notify_token),
+            ("auth.admin_token", &self.admin_token),
+        ] {
+            if token.as_deref() == Some("") {
+                return Err(format!("{name}: must not be empty"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Log a warning for every endpoint group left unauthenticated.
+    pub fn warn_unprotected(&self) {
+        if self.api_tokens.is_empty() {
+            warn!(
+                "No API tokens configured: /chat and /jobs accept any caller on untrusted and verified channels"
+            );
+        }
+        if self.status_token.is_none() {
+            warn!("No status token configured: /api/status is unauthenticated");
+        }
+        if self.notify_token.is_none() {
+            warn!(
+                "No notify token configured: voice-echo notification endpoints are unauthenticated"
+            );
+        }
+        if self.admin_token.is_none() {
+            warn!("No admin token configured: /admin endpoints are disabled");
+        }
+    }
+}
+
+/// Who is calling the chat/job API.
+pub enum Caller {
+    /// No API tokens are configured; any channel short of trusted may be
+    /// used.
+    Open,
+    Token(ApiToken),
+}
+
+impl Caller {
+    /// Whether the caller may use `channel`, whose trust level is `level`.
+    /// Trusted channels always need a token that lists them.
+    pub fn allows(&self, channel: &str, level: TrustLevel) -> bool {
+        match self {
+            Caller::Open => level != TrustLevel::Trusted,
+            Caller::Token(t) => t.channels.iter().any(|c| c == channel),
+        }
+    }
+
+    /// Channel to use when the request does not name one.
+    pub fn default_channel(&self) -> Option<&str> {
+        match self {
+            Caller::Open => None,
+            Caller::Token(t) => t.channels.first().map(String::as_str),
+        }
+    }
+}
+
+/// Identify the caller of a chat/job endpoint from its bearer token.
+pub fn api_caller(
+    auth: &AuthConfig,
+    headers: &HeaderMap,
+) -> Result<Caller, (StatusCode, Json<Value>)> {
+    if auth.api_tokens.is_empty() {
+        return Ok(Caller::Open);
+    }
+    let presented = bearer(headers).ok_or_else(unauthorized)?;
+    auth.api_tokens
+        .iter()
+        .find(|t| constant_time_eq(&t.token, presented))
+        .map(|t| Caller::Token(t.clone()))
+        .ok_or_else(unauthorized)
+}
+
+pub fn forbidden_channel(caller: &Caller, channel: &str) -> (StatusCode, Json<Value>) {
+    let message = match caller {
+        Caller::Open => format!("Channel '{channel}' is trusted and needs an API token"),
+        Caller::Token(_) => format!("Token is not allowed on channel '{channel}'"),
+    };
+    (StatusCode::FORBIDDEN, Json(json!({"response": message})))
+}
+
+/// Middleware for `/api/status`, `/api/queue`, `/api/voice-sessions` and
+/// `/metrics`.
+pub async fn require_status(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let token = state.settings.load().config.auth.status_token.clone();
+    require(token.as_deref(), req, next).await
+}
+
+/// Middleware for voice-echo notification endpoints.
+pub async fn require_notify(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let token = state.settings.load().config.auth.notify_token.clone();
+    require(token.as_deref(), req, next).await
+}
+
+/// Middleware for `/admin/*`.
+pub async fn require_admin(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let token = state.settings.load().config.auth.admin_token.clone();
+    match admin_access(token.as_deref(), req.headers()) {
+        Ok(()) => next.run(req).await,
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Admin endpoints fail closed: they can release held requests, so with no
+/// admin token configured every call is refused.
+fn admin_access(
+    expected: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let Some(expected) = expected else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"response": "Admin endpoints are disabled: no admin token configured"})),
+        ));
+    };
+    if bearer(headers).is_some_and(|t| constant_time_eq(expected, t)) {
+        Ok(())
+    } else {
+        Err(unauthorized())
+    }
+}
+
+async fn require(expected: Option<&str>, req: Request, next: Next) -> Response {
+    if let Some(expected) = expected {
+        let ok = bearer(req.headers()).is_some_and(|t| constant_time_eq(expected, t));
+        if !ok {
+            return unauthorized().into_response();
+        }
+    }
+    next.run(req).await
+}
+
+fn unauthorized() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(json!({"response": "Missing or invalid bearer token"})),
+    )
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Compare without short-circuiting on the first differing byte.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(token: &str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {token}").parse().unwrap(),
+        );
+        h
+    }
+
+    fn auth() -> AuthConfig {
+        AuthConfig {
+            api_tokens: vec![ApiToken {
+                name: Some("discord bot".into()),
+                token: "secret".into(),
+                channels: vec!["discord".into(), "voice".into()],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn no_tokens_means_open_except_trusted() {
+        let caller = api_caller(&AuthConfig::default(), &HeaderMap::new())
+            .ok()
+            .unwrap();
+        assert!(caller.allows("discord", TrustLevel::Verified));
+        assert!(caller.allows("webhook", TrustLevel::Untrusted));
+        assert!(!caller.allows("system", TrustLevel::Trusted));
+    }
+
+    #[test]
+    fn token_scopes_channels() {
+        let caller = api_caller(&auth(), &headers("secret")).ok().unwrap();
+        assert!(caller.allows("discord", TrustLevel::Verified));
+        assert!(!caller.allows("system", TrustLevel::Trusted));
+        assert_eq!(caller.default_channel(), Some("discord"));
+    }
+
+    #[test]
+    fn wrong_or_missing_token_rejected() {
+        let err = api_caller(&auth(), &headers("nope")).err().unwrap();
+        assert_eq!(err.0, StatusCode::UNAUTHORIZED);
+        assert!(api_caller(&auth(), &HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn admin_endpoints_fail_closed() {
+        let err = admin_access(None, &headers("anything")).unwrap_err();
+        assert_eq!(err.0, StatusCode::FORBIDDEN);
+        assert_eq!(
+            admin_access(Some("adm"), &headers("nope")).unwrap_err().0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(admin_access(Some("adm"), &headers("adm")).is_ok());
+    }
+
+    #[test]
+    fn parse_env_tokens() {
+        let tokens = AuthConfig::parse_api_tokens("abc:discord,voice; def:system").unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].channels, vec!["discord", "voice"]);
+        assert_eq!(tokens[1].token, "def");
+        assert!(AuthConfig::parse_api_tokens("nocolon").is_err());
+    }
+
+    #[test]
+    fn empty_channel_list_is_invalid() {
+        let auth = AuthConfig {
+            api_tokens: AuthConfig::parse_api_tokens("abc:").unwrap(),
+            ..Default::default()
+        };
+        assert!(auth.validate().unwrap_err().contains("channels"));
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Child;
+use tracing::{info, warn};
+
+use crate::alerts;
+use crate::config::Config;
+use crate::handlers::chat::{CallbackConfig, RequestMetadata};
+use crate::metrics::METRICS;
+use crate::speech;
+use crate::store;
+use crate::webhook::Webhooks;
+
+/// Longest a callback command may run.
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+/// Discord rejects messages longer than this many characters.
+const DISCORD_LIMIT: usize = 2000;
+
+/// Where a response can be delivered besides the original caller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// POST to `url`, retried until delivered (see [`crate::webhook`]).
+    Webhook,
+    /// Post to `metadata.discord_channel_id` with the bot token.
+    Discord,
+    /// Inject into the voice-echo call `metadata.call_sid`.
+    Voice,
+    /// Append to the `[callbacks.files]` entry named by `target`.
+    File,
+    /// Pipe to the `[callbacks.commands]` entry named by `target`.
+    Exec,
+}
+
+/// Callback types by the name used in `callback.type`.
+const REGISTRY: &[(&str, Kind)] = &[
+    ("webhook", Kind::Webhook),
+    ("discord", Kind::Discord),
+    ("voice", Kind::Voice),
+    ("file", Kind::File),
+    ("exec", Kind::Exec),
+];
+
+impl Kind {
+    pub fn lookup(name: &str) -> Option<Self> {
+        REGISTRY.iter().find(|(n, _)| *n == name).map(|(_, k)| *k)
+    }
+
+    fn name(self) -> &'static str {
+        REGISTRY
+            .iter()
+            .find(|(_, k)| *k == self)
+            .map_or("?", |(n, _)| n)
+    }
+}
+
+/// Check a request's callback before it is queued, so a bad one is
+/// reported to the caller instead of failing after Claude has run.
+pub fn validate(
+    callback: &CallbackConfig,
+    metadata: &RequestMetadata,
+    config: &Config,
+) -> Result<Kind, String> {
+    let name = callback.callback_type.as_str();
+    let Some(kind) = Kind::lookup(name) else {
+        let known: Vec<&str> = REGISTRY.iter().map(|(n, _)| *n).collect();
+        return Err(format!(
+            "Unknown callback type '{name}' (expected one of: {})",
+            known.join(", ")
+        ));
+    };
+    match kind {
+        Kind::Webhook if callback.url.is_none() => Err("webhook callback needs a url".into()),
+        Kind::Discord if metadata.discord_channel_id.is_none() => {
+            Err("discord callback needs metadata.discord_channel_id".into())
+        }
+        Kind::Discord if config.discord_bot_token.is_none() => {
+            Err("discord callbacks need a Discord bot token configured".into())
+        }
+        Kind::Voice if metadata.call_sid.is_none() => {
+            Err("voice callback needs metadata.call_sid".into())
+        }
+        Kind::Voice if config.voice_echo_url.is_none() => {
+            Err("voice callbacks need a voice-echo URL configured".into())
+        }
+        Kind::File => target(callback, &config.callback_files).map(|_| kind),
+        Kind::Exec => target(callback, &config.callback_commands).map(|_| kind),
+        _ => Ok(kind),
+    }
+}
+
+/// The configured entry named by `callback.target`.
+fn target<'a, T>(
+    callback: &CallbackConfig,
+    entries: &'a BTreeMap<String, T>,
+) -> Result<&'a T, String> {
+    let kind = &callback.callback_type;
+    let Some(name) = &callback.target else {
+        return Err(format!("{kind} callback needs a target"));
+    };
+    entries
+        .get(name)
+        .ok_or_else(|| format!("Unknown {kind} callback target '{name}'"))
+}
+
+/// A finished response on its way to a callback.
+#[derive(Clone)]
+pub struct Outgoing {
+    pub request_id: u64,
+    pub channel: String,
+    pub sender: String,
+    pub metadata: RequestMetadata,
+    pub response: String,
+}
+
+impl Outgoing {
+    /// JSON body for webhooks and file entries.
+    fn payload(&self) -> serde_json::Value {
+        serde_json::json!({
+            "request_id": self.request_id,
+            "response": &self.response,
+            "channel": &self.channel,
+            "sender": &self.sender,
+            "metadata": {
+                "call_sid": &self.metadata.call_sid,
+                "discord_channel_id": &self.metadata.discord_channel_id,
+                "workflow_id": &self.metadata.workflow_id,
+            }
+        })
+    }
+}
+
+/// Hand a response to its callback. Webhooks are queued for reliable
+/// delivery; the other types are tried once and failures are logged.
+/// Returns false if the callback could not be delivered.
+pub async fn dispatch(
+    callback: &CallbackConfig,
+    out: Outgoing,
+    config: &Config,
+    webhooks: &Webhooks,
+    client: &reqwest::Client,
+) -> bool {
+    // Validated at submission, but a reload may have removed a target.
+    let kind = match validate(callback, &out.metadata, config) {
+        Ok(kind) => kind,
+        Err(e) => {
+            warn!(
+                "[{}] Callback for request #{} skipped: {e}",
+                out.channel, out.request_id
+            );
+            return false;
+        }
+    };
+
+    let result = match kind {
+        Kind::Webhook => {
+            let url = callback.url.as_deref().unwrap_or_default();
+            webhooks.enqueue(out.request_id, url, out.payload()).await;
+            return true;
+        }
+        Kind::Discord => discord(client, config, &out).await,
+        Kind::Voice => {
+            let call_sid = out.metadata.call_sid.as_deref().unwrap_or_default();
+            let text = speech::for_voice(&out.response, config).await;
+            inject_voice(client, config, call_sid, &text).await
+        }
+        Kind::File => append_file(&config.callback_files, callback, &out),
+        Kind::Exec => exec(&config.callback_commands, callback, &out).await,
+    };
+
+    let name = kind.name();
+    match result {
+        Ok(()) => {
+            METRICS.callbacks.inc(&format!("{name}_success"));
+            info!(
+                "[{}] {name} callback for request #{} delivered",
+                out.channel, out.request_id
+            );
+            true
+        }
+        Err(e) => {
+            METRICS.callbacks.inc(&format!("{name}_error"));
+            warn!(
+                "[{}] {name} callback for request #{} failed: {e}",
+                out.channel, out.request_id
+            );
+            false
+        }
+    }
+}
+
+/// POST `text` to voice-echo's `/api/inject` for an active call.
+pub async fn inject_voice(
+    client: &reqwest::Client,
+    config: &Config,
+    call_sid: &str,
+    text: &str,
+) -> Result<(), String> {
+    let Some(voice_url) = &config.voice_echo_url else {
+        return Err("no voice-echo URL configured".into());
+    };
+    let inject_url = format!("{}/api/inject", voice_url.trim_end_matches('/'));
+    let mut req = client.post(&inject_url).json(&serde_json::json!({
+        "call_sid": call_sid,
+        "text": text,
+    }));
+    if let Some(token) = &config.voice_echo_token {
+        req = req.bearer_auth(token);
+    }
+    match req.send().await {
+        Ok(resp) if resp.status().is_success() => {
+            METRICS.voice_injects.inc("success");
+            Ok(())
+        }
+        Ok(resp) => {
+            METRICS.voice_injects.inc("http_error");
+            Err(format!("HTTP {}", resp.status()))
+        }
+        Err(e) => {
+            METRICS.voice_injects.inc("error");
+            Err(e.to_string())
+        }
+    }
+}
+
+async fn discord(client: &reqwest::Client, config: &Config, out: &Outgoing) -> Result<(), String> {
+    let (Some(token), Some(channel_id)) =
+        (&config.discord_bot_token, &out.metadata.discord_channel_id)
+    else {
+        return Err("no bot token or channel id".into());
+    };
+    for chunk in chunks(&out.response, DISCORD_LIMIT) {
+        match alerts::post_discord(client, token, channel_id, chunk).await {
+            Ok(r) if r.status().is_success() => {}
+            Ok(r) => return Err(format!("HTTP {}", r.status())),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Split `text` into pieces of at most `max` characters.
+fn chunks(text: &str, max: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.chars().count() > max {
+        let (end, _) = rest.char_indices().nth(max).unwrap_or((rest.len(), ' '));
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    pieces.push(rest);
+    pieces
+}
+
+fn append_file(
+    files: &BTreeMap<String, String>,
+    callback: &CallbackConfig,
+    out: &Outgoing,
+) -> Result<(), String> {
+    let path = target(callback, files)?;
+    let mut entry = out.payload();
+    entry["completed_unix"] = store::now_unix().into();
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{path}: {e}"))?;
+    writeln!(f, "{entry}").map_err(|e| format!("{path}: {e}"))
+}
+
+/// Run the configured command with the response on stdin and the request
+/// details in `BRIDGE_ECHO_*` environment variables.
+async fn exec(
+    commands: &BTreeMap<String, Vec<String>>,
+    callback: &CallbackConfig,
+    out: &Outgoing,
+) -> Result<(), String> {
+    let argv = target(callback, commands)?;
+    let (program, args) = argv.split_first().ok_or("empty command")?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .env("BRIDGE_ECHO_REQUEST_ID", out.request_id.to_string())
+        .env("BRIDGE_ECHO_CHANNEL", &out.channel)
+        .env("BRIDGE_ECHO_SENDER", &out.sender)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("{program}: {e}"))?;
+
+    match feed(&mut child, out.response.as_bytes(), EXEC_TIMEOUT).await {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{program} exited with {status}")),
+        Err(e) => Err(format!("{program}: {e}")),
+    }
+}
+
+/// Write `input` to the child's stdin, close it and wait for the child to
+/// exit, killing it if all of that takes longer than `limit`. A command
+/// that never reads its input would otherwise block the write forever.
+async fn feed(child: &mut Child, input: &[u8], limit: Duration) -> Result<ExitStatus, String> {
+    let stdin = child.stdin.take();
+    let run = async {
+        if let Some(mut stdin) = stdin {
+            stdin.write_all(input).await?;
+            drop(stdin);
+        }
+        child.wait().await
+    };
+    match tokio::time::timeout(limit, run).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => {
+            let _ = child.kill().await;
+            Err("timed out".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn callback(kind: &str, target: Option<&str>) -> CallbackConfig {
+        CallbackConfig {
+            callback_type: kind.into(),
+            url: None,
+            target: target.map(String::from),
+        }
+    }
+
+    fn outgoing(response: &str) -> Outgoing {
+        Outgoing {
+            request_id: 3,
+            channel: "n8n".into(),
+            sender: "D".into(),
+            metadata: RequestMetadata::default(),
+            response: response.into(),
+        }
+    }
+
+    #[test]
+    fn unknown_and_incomplete_callbacks_are_rejected() {
+        let config = Config::default();
+        let metadata = RequestMetadata::default();
+        let err = validate(&callback("carrier-pigeon", None), &metadata, &config).unwrap_err();
+        assert!(err.contains("carrier-pigeon"), "{err}");
+        assert!(validate(&callback("webhook", None), &metadata, &config).is_err());
+        assert!(validate(&callback("discord", None), &metadata, &config).is_err());
+        assert!(validate(&callback("voice", None), &metadata, &config).is_err());
+        assert!(validate(&callback("file", Some("log")), &metadata, &config).is_err());
+
+        let config = Config {
+            callback_files: [("log".to_string(), "/tmp/x.jsonl".to_string())].into(),
+            ..Config::default()
+        };
+        assert_eq!(
+            validate(&callback("file", Some("log")), &metadata, &config),
+            Ok(Kind::File)
+        );
+        assert!(validate(&callback("exec", Some("log")), &metadata, &config).is_err());
+    }
+
+    #[test]
+    fn long_discord_messages_are_split() {
+        let text = "é".repeat(4500);
+        let pieces = chunks(&text, DISCORD_LIMIT);
+        let lens: Vec<usize> = pieces.iter().map(|p| p.chars().count()).collect();
+        assert_eq!(lens, [2000, 2000, 500]);
+        assert_eq!(chunks("hi", DISCORD_LIMIT), ["hi"]);
+    }
+
+    #[test]
+    fn file_callbacks_append_jsonl() {
+        let path =
+            std::env::temp_dir().join(format!("bridge-echo-cb-{}.jsonl", std::process::id()));
+        let files = [("log".to_string(), path.to_string_lossy().into_owned())].into();
+        let cb = callback("file", Some("log"));
+        append_file(&files, &cb, &outgoing("one")).unwrap();
+        append_file(&files, &cb, &outgoing("two")).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["response"], "two");
+        assert_eq!(lines[1]["request_id"], 3);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_callbacks_get_the_response_on_stdin() {
+        let path = std::env::temp_dir().join(format!("bridge-echo-exec-{}", std::process::id()));
+        let script = format!(
+            "cat > {} && test \"$BRIDGE_ECHO_CHANNEL\" = n8n",
+            path.display()
+        );
+        let commands = [(
+            "save".to_string(),
+            vec!["sh".to_string(), "-c".to_string(), script],
+        )]
+        .into();
+        exec(
+            &commands,
+            &callback("exec", Some("save")),
+            &outgoing("piped"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "piped");
+
+        let failing = [("fail".to_string(), vec!["false".to_string()])].into();
+        assert!(
+            exec(&failing, &callback("exec", Some("fail")), &outgoing("x"))
+                .await
+                .is_err()
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn commands_that_ignore_stdin_are_killed() {
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("10")
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        // Far more than a pipe buffer holds, so the write can only finish if
+        // the command reads it.
+        let input = vec![b'x'; 1 << 20];
+        let err = feed(&mut child, &input, Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "timed out");
+        assert!(child.try_wait().unwrap().is_some());
+    }
+}
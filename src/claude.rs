@@ -1,24 +1,71 @@
-use tokio::process::Command;
-use tracing::warn;
+use std::process::{Output, Stdio};
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::metrics::METRICS;
+use crate::tracker::Outcome;
+use crate::trust::{PermissionMode, ToolPolicy};
 
 pub struct ClaudeResponse {
     pub text: String,
     pub session_id: Option<String>,
+    pub outcome: Outcome,
+    /// Exit code of the CLI, when it exited on its own.
+    pub exit_code: Option<i32>,
+}
+
+/// Incremental output from a streaming (`stream-json`) invocation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// Text from an assistant message.
+    Text(String),
+    /// The model invoked a tool.
+    ToolUse { name: String, input: Value },
+}
+
+/// Everything needed to run one Claude CLI call.
+pub struct Invocation<'a> {
+    pub claude_bin: &'a str,
+    pub prompt: &'a str,
+    pub home: &'a str,
+    pub session_id: Option<&'a str>,
+    pub self_doc: Option<&'a str>,
+    /// Permission mode, tool lists and working directory for the request's
+    /// trust level.
+    pub policy: &'a ToolPolicy,
+    pub timeout: Duration,
+    /// When set, run in `stream-json` mode and forward partial output here.
+    pub events: Option<&'a mpsc::UnboundedSender<StreamEvent>>,
+    /// Fires to kill the subprocess early. A dropped sender is ignored.
+    pub cancel: Option<oneshot::Receiver<()>>,
 }
 
-pub async fn invoke(
-    claude_bin: &str,
-    prompt: &str,
-    home: &str,
-    session_id: Option<&str>,
-    self_doc: Option<&str>,
-) -> ClaudeResponse {
+pub async fn invoke(inv: Invocation<'_>) -> ClaudeResponse {
+    let Invocation {
+        claude_bin,
+        prompt,
+        home,
+        session_id,
+        self_doc,
+        policy,
+        timeout,
+        events,
+        cancel,
+    } = inv;
+
     let mut cmd = Command::new(claude_bin);
-    cmd.arg("-p")
-        .arg(prompt)
-        .arg("--output-format")
-        .arg("json")
-        .arg("--dangerously-skip-permissions");
+    cmd.arg("-p").arg(prompt).arg("--output-format");
+    if events.is_some() {
+        cmd.arg("stream-json").arg("--verbose");
+    } else {
+        cmd.arg("json");
+    }
+    add_policy_args(&mut cmd, policy);
 
     if let Some(sid) = session_id {
         cmd.arg("-r").arg(sid);
@@ -30,34 +77,225 @@ pub async fn invoke(
 
     cmd.env("CLAUDE_CODE_ENTRYPOINT", "cli");
     cmd.env("HOME", home);
-    cmd.current_dir(home);
+    match policy.workdir.as_deref() {
+        Some(dir) => {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                return failed(format!("Error creating working directory {dir}: {e}"));
+            }
+            cmd.current_dir(dir);
+        }
+        None => {
+            cmd.current_dir(home);
+        }
+    }
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    // Run Claude in its own process group so a timeout can take down any
+    // tools it spawned, not just the CLI itself.
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            METRICS.claude_exits.inc("spawn_error");
+            return failed(format!("Error running Claude: {e}"));
+        }
+    };
+    let pid = child.id();
+
+    let run = async {
+        match events {
+            Some(tx) => stream_output(child, tx).await,
+            None => child.wait_with_output().await,
+        }
+    };
+
+    let cancelled = async {
+        let fired = match cancel {
+            Some(rx) => rx.await.is_ok(),
+            None => false,
+        };
+        if !fired {
+            std::future::pending::<()>().await;
+        }
+    };
 
-    match cmd.output().await {
-        Ok(output) => {
-            if !output.status.success() {
+    let result = tokio::select! {
+        result = tokio::time::timeout(timeout, run) => result,
+        () = cancelled => {
+            info!("Claude subprocess cancelled, killing process group");
+            METRICS.claude_exits.inc("cancelled");
+            if let Some(pid) = pid {
+                kill_process_group(pid);
+            }
+            return ClaudeResponse {
+                text: "[cancelled] Request was cancelled.".into(),
+                session_id: None,
+                outcome: Outcome::Cancelled,
+                exit_code: None,
+            };
+        }
+    };
+
+    match result {
+        Ok(Ok(output)) => {
+            let code = output.status.code();
+            METRICS
+                .claude_exits
+                .inc(&code.map_or_else(|| "signal".to_string(), |c| c.to_string()));
+            let mut response = if output.status.success() {
+                parse_output(&String::from_utf8_lossy(&output.stdout))
+            } else {
                 let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-                return ClaudeResponse {
-                    text: if stderr.is_empty() {
-                        "Claude returned an error.".into()
-                    } else {
-                        stderr
-                    },
-                    session_id: None,
-                };
+                failed(if stderr.is_empty() {
+                    "Claude returned an error.".into()
+                } else {
+                    stderr
+                })
+            };
+            response.exit_code = code;
+            response
+        }
+        Ok(Err(e)) => {
+            METRICS.claude_exits.inc("error");
+            failed(format!("Error running Claude: {e}"))
+        }
+        Err(_) => {
+            METRICS.claude_exits.inc("timeout");
+            warn!(
+                "Claude subprocess timed out after {}s, killing process group",
+                timeout.as_secs()
+            );
+            if let Some(pid) = pid {
+                kill_process_group(pid);
+            }
+            ClaudeResponse {
+                text: format!(
+                    "[timeout] Claude did not respond within {}s.",
+                    timeout.as_secs()
+                ),
+                session_id: None,
+                outcome: Outcome::TimedOut,
+                exit_code: None,
             }
+        }
+    }
+}
 
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            parse_output(&stdout)
+fn add_policy_args(cmd: &mut Command, policy: &ToolPolicy) {
+    match policy.mode {
+        PermissionMode::BypassPermissions => {
+            cmd.arg("--dangerously-skip-permissions");
+        }
+        mode => {
+            cmd.arg("--permission-mode").arg(mode.as_str());
+        }
+    }
+    if !policy.allowed_tools.is_empty() {
+        cmd.arg("--allowedTools")
+            .arg(policy.allowed_tools.join(","));
+    }
+    if !policy.disallowed_tools.is_empty() {
+        cmd.arg("--disallowedTools")
+            .arg(policy.disallowed_tools.join(","));
+    }
+}
+
+/// Read `stream-json` output line by line, forwarding assistant text and
+/// tool use as they arrive. The returned stdout holds only the final
+/// `result` line, which has the same shape as `--output-format json`.
+async fn stream_output(
+    mut child: Child,
+    events: &mpsc::UnboundedSender<StreamEvent>,
+) -> std::io::Result<Output> {
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_task = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf).await;
+        buf
+    });
+
+    let mut result = Vec::new();
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        let Ok(value) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if value.get("type").and_then(Value::as_str) == Some("result") {
+            result = line.into_bytes();
+            continue;
+        }
+        for event in stream_events(&value) {
+            let _ = events.send(event);
+        }
+    }
+
+    let status = child.wait().await?;
+    let stderr = stderr_task.await.unwrap_or_default();
+    Ok(Output {
+        status,
+        stdout: result,
+        stderr,
+    })
+}
+
+/// Extract text and tool-use events from one `stream-json` line.
+fn stream_events(value: &Value) -> Vec<StreamEvent> {
+    if value.get("type").and_then(Value::as_str) != Some("assistant") {
+        return Vec::new();
+    }
+
+    let Some(blocks) = value.pointer("/message/content").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+
+    blocks
+        .iter()
+        .filter_map(|block| match block.get("type")?.as_str()? {
+            "text" => Some(StreamEvent::Text(block.get("text")?.as_str()?.to_string())),
+            "tool_use" => Some(StreamEvent::ToolUse {
+                name: block.get("name")?.as_str()?.to_string(),
+                input: block.get("input").cloned().unwrap_or(Value::Null),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn failed(text: String) -> ClaudeResponse {
+    ClaudeResponse {
+        text,
+        session_id: None,
+        outcome: Outcome::Failed,
+        exit_code: None,
+    }
+}
+
+/// Kill every process in the group led by `pid`. The direct child is also
+/// killed on drop, but tools it spawned would otherwise be left running.
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    // SAFETY: killpg has no memory-safety preconditions; a stale pgid
+    // just yields ESRCH.
+    let rc = unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+    if rc != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            warn!("failed to kill Claude process group {pid}: {err}");
         }
-        Err(e) => ClaudeResponse {
-            text: format!("Error running Claude: {e}"),
-            session_id: None,
-        },
     }
 }
 
+#[cfg(not(unix))]
+fn kill_process_group(_pid: u32) {}
+
 fn parse_output(stdout: &str) -> ClaudeResponse {
-    match serde_json::from_str::<serde_json::Value>(stdout) {
+    match serde_json::from_str::<Value>(stdout) {
         Ok(parsed) => {
             let text = parsed
                 .get("result")
@@ -77,10 +315,13 @@ fn parse_output(stdout: &str) -> ClaudeResponse {
                     text
                 },
                 session_id,
+                outcome: Outcome::Completed,
+                exit_code: None,
             }
         }
         Err(e) => {
             warn!("failed to parse Claude JSON output: {e}");
+            METRICS.parse_failures.inc();
             let text = stdout.trim().to_string();
             ClaudeResponse {
                 text: if text.is_empty() {
@@ -89,6 +330,8 @@ fn parse_output(stdout: &str) -> ClaudeResponse {
                     text
                 },
                 session_id: None,
+                outcome: Outcome::Completed,
+                exit_code: None,
             }
         }
     }
@@ -97,6 +340,7 @@ fn parse_output(stdout: &str) -> ClaudeResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::trust::Permissions;
 
     #[test]
     fn parse_valid_json() {
@@ -135,4 +379,129 @@ mod tests {
         let resp = parse_output(input);
         assert_eq!(resp.text, "No response from Claude.");
     }
+
+    #[test]
+    fn stream_events_from_assistant_message() {
+        let line = serde_json::json!({
+            "type": "assistant",
+            "message": {"content": [
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "name": "Bash", "input": {"command": "ls"}},
+            ]}
+        });
+        let events = stream_events(&line);
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Text("Checking.".into()),
+                StreamEvent::ToolUse {
+                    name: "Bash".into(),
+                    input: serde_json::json!({"command": "ls"}),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_events_ignores_other_lines() {
+        let line = serde_json::json!({"type": "system", "subtype": "init"});
+        assert!(stream_events(&line).is_empty());
+    }
+
+    #[test]
+    fn parse_stream_result_line() {
+        let input = r#"{"type":"result","subtype":"success","result":"Done.","session_id":"s-1"}"#;
+        let resp = parse_output(input);
+        assert_eq!(resp.text, "Done.");
+        assert_eq!(resp.session_id, Some("s-1".into()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn timeout_kills_process_group() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("bridge-echo-timeout-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("claude");
+        let marker = dir.join("grandchild.pid");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\nsleep 30 &\necho $! > {}\nwait\n",
+                marker.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let resp = invoke(Invocation {
+            claude_bin: script.to_str().unwrap(),
+            prompt: "hello",
+            home: dir.to_str().unwrap(),
+            session_id: None,
+            self_doc: None,
+            policy: &Permissions::default().trusted,
+            timeout: Duration::from_millis(300),
+            events: None,
+            cancel: None,
+        })
+        .await;
+        assert_eq!(resp.outcome, Outcome::TimedOut);
+        assert!(resp.text.contains("timeout"));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let pid: i32 = std::fs::read_to_string(&marker)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        // A killed grandchild may linger as a zombie until init reaps it.
+        let alive = std::fs::read_to_string(format!("/proc/{pid}/stat"))
+            .map(|stat| !stat.contains(") Z "))
+            .unwrap_or(false);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(!alive, "grandchild process survived the timeout");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn untrusted_policy_restricts_cli() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("bridge-echo-policy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("claude");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nprintf '{\"result\":\"%s | %s\"}' \"$*\" \"$(pwd)\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let sandbox = dir.join("sandbox");
+        let policy = ToolPolicy {
+            workdir: Some(sandbox.to_string_lossy().into_owned()),
+            ..Permissions::default().untrusted
+        };
+
+        let resp = invoke(Invocation {
+            claude_bin: script.to_str().unwrap(),
+            prompt: "hello",
+            home: dir.to_str().unwrap(),
+            session_id: None,
+            self_doc: None,
+            policy: &policy,
+            timeout: Duration::from_secs(5),
+            events: None,
+            cancel: None,
+        })
+        .await;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(resp.outcome, Outcome::Completed, "{}", resp.text);
+        assert!(!resp.text.contains("--dangerously-skip-permissions"));
+        assert!(resp.text.contains("--permission-mode default"));
+        assert!(resp.text.contains("--disallowedTools Bash,Edit"));
+        assert!(resp.text.ends_with(&format!("| {}", sandbox.display())));
+    }
 }
@@ -1,10 +1,35 @@
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::approval;
+use crate::auth::{ApiToken, AuthConfig};
+use crate::injection::{self, InjectionDetector};
+use crate::queue::{PriorityCaps, QueueOptions};
+use crate::ratelimit::Limits;
+use crate::routing::{self, Route};
+use crate::session::SessionScope;
+use crate::trust::{ChannelConfig, PermissionMode, Permissions, TrustLevel, TrustMap};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub host: String,
     pub port: u16,
     pub session_ttl_secs: u64,
+    /// What Claude sessions are keyed on: channel, sender, channel+sender
+    /// or global. Default: channel.
+    pub session_scope: SessionScope,
+    /// Number of Claude subprocesses that may run at once. Requests in the
+    /// same session are still processed one at a time. Default: 1.
+    pub workers: usize,
+    /// Maximum wall-clock time for a single Claude subprocess, in seconds.
+    pub timeout_secs: u64,
     pub claude_bin: String,
     pub self_path: Option<String>,
     pub home: String,
@@ -16,59 +41,943 @@ pub struct Config {
     pub voice_echo_url: Option<String>,
     /// Bearer token for authenticating with voice-echo's API.
     pub voice_echo_token: Option<String>,
+    /// Directory for request history, Claude sessions and voice sessions.
+    /// When unset, everything is kept in memory only.
+    pub data_dir: Option<String>,
+    /// Directory for the audit log. Defaults to `audit/` under `data_dir`;
+    /// with neither set, no audit log is written.
+    pub audit_dir: Option<String>,
+    /// Start a new audit file once the current one reaches this size.
+    pub audit_max_bytes: u64,
+    /// Start a new audit file at the start of each UTC day.
+    pub audit_rotate_daily: bool,
     /// Voice session timeout in seconds. If no voice activity for this
     /// long, the session is considered expired. Default: 300 (5 minutes).
     pub voice_session_timeout_secs: u64,
+    /// Responses longer than this many characters, once adapted for speech,
+    /// are summarized by Claude before being spoken on a call. Unset: never.
+    pub voice_summarize_over: Option<usize>,
+    /// Channel definitions: trust level and default sender per channel.
+    pub channels: TrustMap,
+    /// Extra injection patterns, added to (or replacing) the built-in set.
+    pub injection_patterns: Vec<String>,
+    pub injection_replace_defaults: bool,
+    /// Also scan base64 and ROT13 decodings of each message.
+    pub injection_decode_payloads: bool,
+    /// What to do with detected injections, per trust level.
+    pub injection_policy: injection::Policy,
+    /// Verified and untrusted messages matching any of these are held for
+    /// approval, as requests for tool use.
+    pub approval_tool_patterns: Vec<String>,
+    /// How long a held request waits for a decision before it is rejected.
+    pub approval_timeout_secs: u64,
+    /// Discord channel notified when a request is held.
+    pub approval_discord_channel: Option<String>,
+    /// Rate limits and daily quotas per channel and sender.
+    pub limits: Limits,
+    /// Requests that may wait in the queue before new ones get 503.
+    pub queue_max_depth: usize,
+    /// Head start, in seconds, given to cross-channel priority requests.
+    pub queue_priority_boost_secs: u64,
+    /// Round-robin weight per channel; unlisted channels weigh 1.
+    pub queue_weights: BTreeMap<String, u32>,
+    /// Highest priority a request may ask for at each trust level.
+    pub queue_max_priority: PriorityCaps,
+    /// Key for the HMAC-SHA256 signature on webhook callbacks.
+    pub callback_secret: Option<String>,
+    /// Webhook delivery attempts before a callback is dead-lettered.
+    pub callback_max_attempts: u32,
+    /// Delay before the first webhook retry; doubles with each attempt.
+    pub callback_retry_base_secs: u64,
+    /// Longest delay between webhook retries.
+    pub callback_retry_max_secs: u64,
+    /// JSONL files `file` callbacks may append to, by target name.
+    pub callback_files: BTreeMap<String, String>,
+    /// Commands `exec` callbacks may run, by target name.
+    pub callback_commands: BTreeMap<String, Vec<String>>,
+    /// Where else finished responses go; see [`routing`].
+    pub routes: Vec<Route>,
+    /// Bearer tokens for the HTTP API.
+    pub auth: AuthConfig,
+    /// Claude CLI permission flags and working directory per trust level.
+    pub permissions: Permissions,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".into(),
+            port: 3100,
+            session_ttl_secs: 3600,
+            session_scope: SessionScope::Channel,
+            workers: 1,
+            timeout_secs: 600,
+            claude_bin: "claude".into(),
+            self_path: None,
+            home: env::var("HOME").unwrap_or_else(|_| ".".into()),
+            discord_bot_token: None,
+            discord_alert_channel: None,
+            alert_thresholds_minutes: vec![10, 20, 30],
+            voice_echo_url: None,
+            voice_echo_token: None,
+            data_dir: None,
+            audit_dir: None,
+            audit_max_bytes: 64 * 1024 * 1024,
+            audit_rotate_daily: true,
+            voice_session_timeout_secs: 300,
+            voice_summarize_over: None,
+            channels: TrustMap::default(),
+            injection_patterns: Vec::new(),
+            injection_replace_defaults: false,
+            injection_decode_payloads: true,
+            injection_policy: injection::Policy::default(),
+            approval_tool_patterns: Vec::new(),
+            approval_timeout_secs: 3600,
+            approval_discord_channel: None,
+            limits: Limits::default(),
+            queue_max_depth: 1000,
+            queue_priority_boost_secs: 60,
+            queue_weights: BTreeMap::new(),
+            queue_max_priority: PriorityCaps::default(),
+            callback_secret: None,
+            callback_max_attempts: 8,
+            callback_retry_base_secs: 10,
+            callback_retry_max_secs: 3600,
+            callback_files: BTreeMap::new(),
+            callback_commands: BTreeMap::new(),
+            routes: routing::defaults(),
+            auth: AuthConfig::default(),
+            permissions: Permissions::default(),
+        }
+    }
+}
+
+/// Layout of the TOML config file. Every key is optional; anything left
+/// out keeps its default. Unknown keys are rejected so typos surface at
+/// startup.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    host: Option<String>,
+    port: Option<u16>,
+    workers: Option<usize>,
+    timeout: Option<u64>,
+    claude_bin: Option<String>,
+    self_path: Option<String>,
+    home: Option<String>,
+    data_dir: Option<String>,
+    #[serde(default)]
+    session: SessionSection,
+    #[serde(default)]
+    alerts: AlertsSection,
+    #[serde(default)]
+    voice: VoiceSection,
+    #[serde(default)]
+    channels: BTreeMap<String, ChannelConfig>,
+    #[serde(default)]
+    injection: InjectionSection,
+    #[serde(default)]
+    auth: AuthSection,
+    #[serde(default)]
+    audit: AuditSection,
+    #[serde(default)]
+    permissions: PermissionsSection,
+    #[serde(default)]
+    approval: ApprovalSection,
+    #[serde(default)]
+    limits: Limits,
+    #[serde(default)]
+    queue: QueueSection,
+    #[serde(default)]
+    callbacks: CallbacksSection,
+    routes: Option<Vec<Route>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct SessionSection {
+    ttl: Option<u64>,
+    scope: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct AlertsSection {
+    discord_bot_token: Option<String>,
+    discord_channel: Option<String>,
+    thresholds_minutes: Option<Vec<u64>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct VoiceSection {
+    url: Option<String>,
+    token: Option<String>,
+    session_timeout: Option<u64>,
+    summarize_over: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct InjectionSection {
+    #[serde(default)]
+    patterns: Vec<String>,
+    #[serde(default)]
+    replace_defaults: bool,
+    decode_payloads: Option<bool>,
+    #[serde(default)]
+    policy: injection::Policy,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct QueueSection {
+    max_depth: Option<usize>,
+    priority_boost: Option<u64>,
+    weights: Option<BTreeMap<String, u32>>,
+    #[serde(default)]
+    max_priority: PriorityCaps,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CallbacksSection {
+    secret: Option<String>,
+    max_attempts: Option<u32>,
+    retry_base: Option<u64>,
+    retry_max: Option<u64>,
+    files: Option<BTreeMap<String, String>>,
+    commands: Option<BTreeMap<String, Vec<String>>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ApprovalSection {
+    tool_patterns: Option<Vec<String>>,
+    timeout: Option<u64>,
+    discord_channel: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct AuthSection {
+    status_token: Option<String>,
+    notify_token: Option<String>,
+    admin_token: Option<String>,
+    #[serde(default)]
+    tokens: Vec<ApiToken>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct AuditSection {
+    dir: Option<String>,
+    max_bytes: Option<u64>,
+    rotate_daily: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PermissionsSection {
+    trusted: Option<PolicySection>,
+    verified: Option<PolicySection>,
+    untrusted: Option<PolicySection>,
+}
+
+/// Overrides for one trust level; unset keys keep that level's default.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicySection {
+    mode: Option<PermissionMode>,
+    allowed_tools: Option<Vec<String>>,
+    disallowed_tools: Option<Vec<String>>,
+    workdir: Option<String>,
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self, String> {
-        let port = env::var("BRIDGE_ECHO_PORT")
-            .unwrap_or_else(|_| "3100".into())
-            .parse::<u16>()
-            .map_err(|e| format!("invalid BRIDGE_ECHO_PORT: {e}"))?;
-
-        let session_ttl_secs = env::var("BRIDGE_ECHO_SESSION_TTL")
-            .unwrap_or_else(|_| "3600".into())
-            .parse::<u64>()
-            .map_err(|e| format!("invalid BRIDGE_ECHO_SESSION_TTL: {e}"))?;
-
-        let self_path = env::var("BRIDGE_ECHO_SELF_PATH").ok();
-
-        let home = env::var("BRIDGE_ECHO_HOME")
-            .or_else(|_| env::var("HOME"))
-            .unwrap_or_else(|_| ".".into());
-
-        let discord_bot_token = env::var("BRIDGE_ECHO_DISCORD_BOT_TOKEN").ok();
-        let discord_alert_channel = env::var("BRIDGE_ECHO_DISCORD_ALERT_CHANNEL").ok();
-
-        let alert_thresholds_minutes = env::var("BRIDGE_ECHO_ALERT_THRESHOLDS")
-            .unwrap_or_else(|_| "10,20,30".into())
-            .split(',')
-            .filter_map(|s| s.trim().parse::<u64>().ok())
-            .collect();
+    /// Load configuration: defaults, then the TOML file at `path` (if any),
+    /// then `BRIDGE_ECHO_*` environment variables.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let mut config = Self::default();
+        if let Some(path) = path {
+            let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+            config
+                .apply_file(&text)
+                .map_err(|e| format!("{path}: {e}"))?;
+        }
+        config.apply_env(|name| env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_file(&mut self, text: &str) -> Result<(), String> {
+        let file: FileConfig = toml::from_str(text).map_err(|e| e.to_string())?;
 
-        let voice_echo_url = env::var("BRIDGE_ECHO_VOICE_URL").ok();
-        let voice_echo_token = env::var("BRIDGE_ECHO_VOICE_TOKEN").ok();
+        set(&mut self.host, file.host);
+        set(&mut self.port, file.port);
+        set(&mut self.workers, file.workers);
+        set(&mut self.timeout_secs, file.timeout);
+        set(&mut self.claude_bin, file.claude_bin);
+        set_opt(&mut self.self_path, file.self_path);
+        set(&mut self.home, file.home);
+        set_opt(&mut self.data_dir, file.data_dir);
 
-        let voice_session_timeout_secs = env::var("BRIDGE_ECHO_VOICE_SESSION_TIMEOUT")
-            .unwrap_or_else(|_| "300".into())
-            .parse::<u64>()
-            .map_err(|e| format!("invalid BRIDGE_ECHO_VOICE_SESSION_TIMEOUT: {e}"))?;
+        set(&mut self.session_ttl_secs, file.session.ttl);
+        if let Some(scope) = file.session.scope {
+            self.session_scope = scope.parse().map_err(|e| format!("session.scope: {e}"))?;
+        }
 
-        Ok(Self {
-            host: env::var("BRIDGE_ECHO_HOST").unwrap_or_else(|_| "0.0.0.0".into()),
+        set_opt(&mut self.discord_bot_token, file.alerts.discord_bot_token);
+        set_opt(&mut self.discord_alert_channel, file.alerts.discord_channel);
+        set(
+            &mut self.alert_thresholds_minutes,
+            file.alerts.thresholds_minutes,
+        );
+
+        set_opt(&mut self.voice_echo_url, file.voice.url);
+        set_opt(&mut self.voice_echo_token, file.voice.token);
+        set(
+            &mut self.voice_session_timeout_secs,
+            file.voice.session_timeout,
+        );
+        set_opt(&mut self.voice_summarize_over, file.voice.summarize_over);
+
+        self.channels.extend(file.channels);
+
+        self.injection_patterns = file.injection.patterns;
+        self.injection_replace_defaults = file.injection.replace_defaults;
+        set(
+            &mut self.injection_decode_payloads,
+            file.injection.decode_payloads,
+        );
+        self.injection_policy = file.injection.policy;
+
+        set(
+            &mut self.approval_tool_patterns,
+            file.approval.tool_patterns,
+        );
+        set(&mut self.approval_timeout_secs, file.approval.timeout);
+        set_opt(
+            &mut self.approval_discord_channel,
+            file.approval.discord_channel,
+        );
+        self.limits = file.limits;
+
+        set(&mut self.queue_max_depth, file.queue.max_depth);
+        set(
+            &mut self.queue_priority_boost_secs,
+            file.queue.priority_boost,
+        );
+        set(&mut self.queue_weights, file.queue.weights);
+        self.queue_max_priority = file.queue.max_priority;
+
+        set_opt(&mut self.callback_secret, file.callbacks.secret);
+        set(&mut self.callback_max_attempts, file.callbacks.max_attempts);
+        set(
+            &mut self.callback_retry_base_secs,
+            file.callbacks.retry_base,
+        );
+        set(&mut self.callback_retry_max_secs, file.callbacks.retry_max);
+        set(&mut self.callback_files, file.callbacks.files);
+        set(&mut self.callback_commands, file.callbacks.commands);
+        set(&mut self.routes, file.routes);
+
+        self.auth = AuthConfig {
+            api_tokens: file.auth.tokens,
+            status_token: file.auth.status_token,
+            notify_token: file.auth.notify_token,
+            admin_token: file.auth.admin_token,
+        };
+
+        set_opt(&mut self.audit_dir, file.audit.dir);
+        set(&mut self.audit_max_bytes, file.audit.max_bytes);
+        set(&mut self.audit_rotate_daily, file.audit.rotate_daily);
+
+        for (level, section) in [
+            (TrustLevel::Trusted, file.permissions.trusted),
+            (TrustLevel::Verified, file.permissions.verified),
+            (TrustLevel::Untrusted, file.permissions.untrusted),
+        ] {
+            let Some(section) = section else { continue };
+            let policy = self.permissions.for_level_mut(level);
+            set(&mut policy.mode, section.mode);
+            set(&mut policy.allowed_tools, section.allowed_tools);
+            set(&mut policy.disallowed_tools, section.disallowed_tools);
+            set_opt(&mut policy.workdir, section.workdir);
+        }
+
+        Ok(())
+    }
+
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(v) = var("BRIDGE_ECHO_HOST") {
+            self.host = v;
+        }
+        env_parse(&var, "BRIDGE_ECHO_PORT", &mut self.port)?;
+        env_parse(&var, "BRIDGE_ECHO_SESSION_TTL", &mut self.session_ttl_secs)?;
+        env_parse(&var, "BRIDGE_ECHO_SESSION_SCOPE", &mut self.session_scope)?;
+        env_parse(&var, "BRIDGE_ECHO_WORKERS", &mut self.workers)?;
+        env_parse(
+            &var,
+            "BRIDGE_ECHO_QUEUE_MAX_DEPTH",
+            &mut self.queue_max_depth,
+        )?;
+        env_parse(&var, "BRIDGE_ECHO_TIMEOUT", &mut self.timeout_secs)?;
+        if let Some(v) = var("BRIDGE_ECHO_CLAUDE_BIN") {
+            self.claude_bin = v;
+        }
+        if let Some(v) = var("BRIDGE_ECHO_SELF_PATH") {
+            self.self_path = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_HOME") {
+            self.home = v;
+        }
+        if let Some(v) = var("BRIDGE_ECHO_DISCORD_BOT_TOKEN") {
+            self.discord_bot_token = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_DISCORD_ALERT_CHANNEL") {
+            self.discord_alert_channel = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_APPROVAL_DISCORD_CHANNEL") {
+            self.approval_discord_channel = Some(v);
+        }
+        env_parse(
+            &var,
+            "BRIDGE_ECHO_APPROVAL_TIMEOUT",
+            &mut self.approval_timeout_secs,
+        )?;
+        if let Some(v) = var("BRIDGE_ECHO_ALERT_THRESHOLDS") {
+            self.alert_thresholds_minutes = v
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.parse::<u64>()
+                        .map_err(|e| format!("invalid BRIDGE_ECHO_ALERT_THRESHOLDS: '{s}': {e}"))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(v) = var("BRIDGE_ECHO_VOICE_URL") {
+            self.voice_echo_url = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_VOICE_TOKEN") {
+            self.voice_echo_token = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_CALLBACK_SECRET") {
+            self.callback_secret = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_DATA_DIR") {
+            self.data_dir = Some(v);
+        }
+        env_parse(
+            &var,
+            "BRIDGE_ECHO_VOICE_SESSION_TIMEOUT",
+            &mut self.voice_session_timeout_secs,
+        )?;
+        if let Some(v) = var("BRIDGE_ECHO_AUDIT_DIR") {
+            self.audit_dir = Some(v);
+        }
+        env_parse(
+            &var,
+            "BRIDGE_ECHO_AUDIT_MAX_BYTES",
+            &mut self.audit_max_bytes,
+        )?;
+        if let Some(v) = var("BRIDGE_ECHO_API_TOKENS") {
+            self.auth.api_tokens = AuthConfig::parse_api_tokens(&v)
+                .map_err(|e| format!("invalid BRIDGE_ECHO_API_TOKENS: {e}"))?;
+        }
+        if let Some(v) = var("BRIDGE_ECHO_STATUS_TOKEN") {
+            self.auth.status_token = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_NOTIFY_TOKEN") {
+            self.auth.notify_token = Some(v);
+        }
+        if let Some(v) = var("BRIDGE_ECHO_ADMIN_TOKEN") {
+            self.auth.admin_token = Some(v);
+        }
+        Ok(())
+    }
+
+    /// Human-readable differences from `self` to `new`. Secrets are reported
+    /// as changed without their values.
+    pub fn diff(&self, new: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        macro_rules! diff {
+            ($($field:ident),* $(,)?) => {$(
+                if self.$field != new.$field {
+                    changes.push(format!(
+                        "{}: {:?} -> {:?}",
+                        stringify!($field),
+                        self.$field,
+                        new.$field
+                    ));
+                }
+            )*};
+        }
+        macro_rules! diff_secret {
+            ($($field:ident),* $(,)?) => {$(
+                if self.$field != new.$field {
+                    changes.push(format!("{}: changed", stringify!($field)));
+                }
+            )*};
+        }
+
+        diff!(
+            host,
             port,
             session_ttl_secs,
-            claude_bin: env::var("BRIDGE_ECHO_CLAUDE_BIN").unwrap_or_else(|_| "claude".into()),
+            session_scope,
+            workers,
+            timeout_secs,
+            claude_bin,
             self_path,
             home,
-            discord_bot_token,
             discord_alert_channel,
             alert_thresholds_minutes,
             voice_echo_url,
-            voice_echo_token,
+            data_dir,
+            audit_dir,
+            audit_max_bytes,
+            audit_rotate_daily,
             voice_session_timeout_secs,
-        })
+            voice_summarize_over,
+            injection_patterns,
+            injection_replace_defaults,
+            injection_decode_payloads,
+            injection_policy,
+            approval_tool_patterns,
+            approval_timeout_secs,
+            approval_discord_channel,
+            limits,
+            queue_max_depth,
+            queue_priority_boost_secs,
+            queue_weights,
+            queue_max_priority,
+            callback_max_attempts,
+            callback_retry_base_secs,
+            callback_retry_max_secs,
+            callback_files,
+            callback_commands,
+            routes,
+            permissions,
+        );
+        diff_secret!(discord_bot_token, voice_echo_token, callback_secret, auth);
+        changes.extend(self.channels.diff(&new.channels));
+        changes
+    }
+
+    /// Copy settings that are only read at startup from `running`, returning
+    /// the names of those that differed.
+    pub fn keep_restart_only(&mut self, running: &Config) -> Vec<&'static str> {
+        let mut kept = Vec::new();
+
+        macro_rules! keep {
+            ($($field:ident),* $(,)?) => {$(
+                if self.$field != running.$field {
+                    kept.push(stringify!($field));
+                    self.$field = running.$field.clone();
+                }
+            )*};
+        }
+
+        keep!(
+            host,
+            port,
+            workers,
+            queue_max_depth,
+            queue_priority_boost_secs,
+            queue_weights,
+            data_dir,
+            audit_dir,
+            audit_max_bytes,
+            audit_rotate_daily,
+            session_scope,
+            session_ttl_secs,
+            voice_session_timeout_secs,
+        );
+        kept
+    }
+
+    pub fn queue_options(&self) -> QueueOptions {
+        QueueOptions {
+            max_depth: self.queue_max_depth,
+            priority_boost: Duration::from_secs(self.queue_priority_boost_secs),
+            weights: self.queue_weights.clone(),
+        }
+    }
+
+    /// Where the audit log goes, if anywhere.
+    pub fn audit_path(&self) -> Option<PathBuf> {
+        self.audit_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .or_else(|| self.data_dir.as_ref().map(|d| Path::new(d).join("audit")))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.workers == 0 {
+            return Err("invalid workers: must be at least 1".into());
+        }
+        if self.timeout_secs == 0 {
+            return Err("invalid timeout: must be at least 1 second".into());
+        }
+        if self.audit_max_bytes == 0 {
+            return Err("invalid audit.max_bytes: must be at least 1".into());
+        }
+        if self.queue_max_depth == 0 {
+            return Err("invalid queue.max_depth: must be at least 1".into());
+        }
+        if let Some((channel, _)) = self.queue_weights.iter().find(|(_, w)| **w == 0) {
+            return Err(format!(
+                "invalid queue.weights.{channel}: must be at least 1"
+            ));
+        }
+        if self.callback_max_attempts == 0 {
+            return Err("invalid callbacks.max_attempts: must be at least 1".into());
+        }
+        if self.callback_retry_base_secs == 0 {
+            return Err("invalid callbacks.retry_base: must be at least 1 second".into());
+        }
+        if let Some((name, _)) = self.callback_commands.iter().find(|(_, c)| c.is_empty()) {
+            return Err(format!(
+                "invalid callbacks.commands.{name}: must name a program"
+            ));
+        }
+        if self.approval_timeout_secs == 0 {
+            return Err("invalid approval.timeout: must be at least 1 second".into());
+        }
+        self.auth.validate()?;
+        self.limits.validate()?;
+        approval::tool_patterns(&self.approval_tool_patterns)?;
+        routing::validate(self)?;
+        InjectionDetector::with_patterns(&self.injection_patterns, self.injection_replace_defaults)
+            .map(|_| ())
+    }
+}
+
+fn set<T>(target: &mut T, value: Option<T>) {
+    if let Some(v) = value {
+        *target = v;
+    }
+}
+
+fn set_opt<T>(target: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *target = value;
+    }
+}
+
+fn env_parse<T>(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut T,
+) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(v) = var(name) {
+        *target = v
+            .parse()
+            .map_err(|e| format!("invalid {name}: '{v}': {e}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::Priority;
+    use std::collections::HashMap;
+
+    fn from(file: &str, env: &[(&str, &str)]) -> Result<Config, String> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut config = Config::default();
+        config.apply_file(file)?;
+        config.apply_env(|name| env.get(name).cloned())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn file_values_apply() {
+        let config = from(
+            r#"
+            port = 4000
+            workers = 3
+
+            [session]
+            scope = "sender"
+
+            [alerts]
+            thresholds_minutes = [5]
+
+            [channels.slack]
+            trust = "verified"
+            default_sender = "S"
+            "#,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.workers, 3);
+        assert_eq!(config.session_scope, SessionScope::Sender);
+        assert_eq!(config.alert_thresholds_minutes, vec![5]);
+        assert_eq!(config.channels.trust("slack"), TrustLevel::Verified);
+        assert_eq!(config.channels.default_sender("slack"), Some("S"));
+        assert_eq!(config.channels.trust("system"), TrustLevel::Trusted);
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let config = from("port = 4000", &[("BRIDGE_ECHO_PORT", "5000")]).unwrap();
+        assert_eq!(config.port, 5000);
+    }
+
+    #[test]
+    fn bad_env_value_names_variable() {
+        let err = from("", &[("BRIDGE_ECHO_PORT", "lots")]).unwrap_err();
+        assert!(err.contains("BRIDGE_ECHO_PORT"), "{err}");
+        let err = from("", &[("BRIDGE_ECHO_ALERT_THRESHOLDS", "10,x")]).unwrap_err();
+        assert!(err.contains("'x'"), "{err}");
+    }
+
+    #[test]
+    fn bad_file_values_are_precise() {
+        let err = from("port = \"lots\"", &[]).unwrap_err();
+        assert!(err.contains("port"), "{err}");
+
+        let err = from("[channels.slack]\ntrust = \"sorta\"", &[]).unwrap_err();
+        assert!(err.contains("sorta"), "{err}");
+
+        let err = from("prot = 1", &[]).unwrap_err();
+        assert!(err.contains("prot"), "{err}");
+
+        let err = from("[session]\nscope = \"team\"", &[]).unwrap_err();
+        assert!(err.starts_with("session.scope"), "{err}");
+    }
+
+    #[test]
+    fn diff_lists_changes_and_hides_secrets() {
+        let old = from("timeout = 600\n[alerts]\ndiscord_bot_token = \"a\"", &[]).unwrap();
+        let new = from(
+            "timeout = 900\n[alerts]\ndiscord_bot_token = \"b\"\n[channels.slack]\ntrust = \"verified\"",
+            &[],
+        )
+        .unwrap();
+        let changes = old.diff(&new);
+        assert!(changes.contains(&"timeout_secs: 600 -> 900".to_string()));
+        assert!(changes.contains(&"discord_bot_token: changed".to_string()));
+        assert!(changes
+            .iter()
+            .any(|c| c.starts_with("channels.slack: added")));
+        assert!(!changes.iter().any(|c| c.contains("\"b\"")));
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn restart_only_fields_are_kept() {
+        let running = from("port = 3100\ntimeout = 600", &[]).unwrap();
+        let mut new = from("port = 4000\ntimeout = 900", &[]).unwrap();
+        assert_eq!(new.keep_restart_only(&running), vec!["port"]);
+        assert_eq!(new.port, 3100);
+        assert_eq!(new.timeout_secs, 900);
+    }
+
+    #[test]
+    fn auth_tokens_from_file_and_env() {
+        let config = from(
+            r#"
+            [auth]
+            status_token = "st"
+
+            [[auth.tokens]]
+            name = "discord bot"
+            token = "abc"
+            channels = ["discord"]
+            "#,
+            &[("BRIDGE_ECHO_ADMIN_TOKEN", "adm")],
+        )
+        .unwrap();
+        assert_eq!(config.auth.api_tokens[0].channels, vec!["discord"]);
+        assert_eq!(config.auth.status_token.as_deref(), Some("st"));
+        assert_eq!(config.auth.admin_token.as_deref(), Some("adm"));
+
+        let config = from("", &[("BRIDGE_ECHO_API_TOKENS", "xyz:voice,system")]).unwrap();
+        assert_eq!(config.auth.api_tokens[0].token, "xyz");
+
+        let err = from("[[auth.tokens]]\ntoken = \"a\"\nchannels = []", &[]).unwrap_err();
+        assert!(err.starts_with("auth.tokens[0]"), "{err}");
+    }
+
+    #[test]
+    fn permissions_override_per_level() {
+        let config = from(
+            r#"
+            [permissions.verified]
+            mode = "default"
+            allowed_tools = ["Read", "WebSearch"]
+
+            [permissions.untrusted]
+            workdir = "/srv/sandbox"
+            "#,
+            &[],
+        )
+        .unwrap();
+        let verified = config.permissions.for_level(TrustLevel::Verified);
+        assert_eq!(verified.mode, PermissionMode::Default);
+        assert_eq!(verified.allowed_tools, vec!["Read", "WebSearch"]);
+        let untrusted = config.permissions.for_level(TrustLevel::Untrusted);
+        assert_eq!(untrusted.workdir.as_deref(), Some("/srv/sandbox"));
+        assert!(untrusted.disallowed_tools.iter().any(|t| t == "Bash"));
+
+        let err = from("[permissions.untrusted]\nmode = \"yolo\"", &[]).unwrap_err();
+        assert!(err.contains("yolo"), "{err}");
+    }
+
+    #[test]
+    fn injection_policy_from_file() {
+        let config = from(
+            r#"
+            [injection.policy]
+            untrusted = "block"
+            verified = "quarantine"
+            min_severity = "medium"
+            "#,
+            &[],
+        )
+        .unwrap();
+        let policy = &config.injection_policy;
+        assert_eq!(policy.trusted, injection::Action::Warn);
+        assert_eq!(policy.verified, injection::Action::Quarantine);
+        assert_eq!(policy.untrusted, injection::Action::Block);
+        assert_eq!(policy.min_severity, injection::Severity::Medium);
+
+        let err = from(
+            "[injection.policy]
+verified = \"drop\"",
+            &[],
+        )
+        .unwrap_err();
+        assert!(err.contains("drop"), "{err}");
+    }
+
+    #[test]
+    fn approval_settings() {
+        let config = from(
+            r#"
+            [approval]
+            tool_patterns = ["(?i)\\brun\\b"]
+            discord_channel = "123"
+            "#,
+            &[("BRIDGE_ECHO_APPROVAL_TIMEOUT", "600")],
+        )
+        .unwrap();
+        assert_eq!(config.approval_tool_patterns.len(), 1);
+        assert_eq!(config.approval_discord_channel.as_deref(), Some("123"));
+        assert_eq!(config.approval_timeout_secs, 600);
+
+        let err = from("[approval]\ntool_patterns = [\"(\"]", &[]).unwrap_err();
+        assert!(err.starts_with("approval.tool_patterns[0]"), "{err}");
+    }
+
+    #[test]
+    fn limits_from_file() {
+        let config = from(
+            r#"
+            [limits.sender]
+            per_minute = 6
+            daily = 200
+
+            [limits.channels.voice]
+            per_minute = 60
+            burst = 10
+            "#,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.limits.sender.per_minute, Some(6));
+        assert_eq!(config.limits.channels["voice"].burst, Some(10));
+        assert_eq!(config.limits.channel.per_minute, None);
+
+        let err = from("[limits.senders.D]\nper_minute = 0", &[]).unwrap_err();
+        assert!(err.contains("limits.senders.D.per_minute"), "{err}");
+    }
+
+    #[test]
+    fn queue_settings_are_restart_only() {
+        let running = from("[queue]\nmax_depth = 50\nweights = { voice = 3 }", &[]).unwrap();
+        assert_eq!(running.queue_options().max_depth, 50);
+        assert_eq!(running.queue_weights["voice"], 3);
+
+        let mut reloaded = from("[queue]\nmax_depth = 10", &[]).unwrap();
+        let kept = reloaded.keep_restart_only(&running);
+        assert!(kept.contains(&"queue_max_depth"));
+        assert!(kept.contains(&"queue_weights"));
+        assert_eq!(reloaded.queue_max_depth, 50);
+
+        assert!(from("[queue]\nweights = { voice = 0 }", &[]).is_err());
+    }
+
+    #[test]
+    fn priority_caps_reload() {
+        let running = Config::default();
+        let mut reloaded = from("[queue.max_priority]\nverified = \"urgent\"", &[]).unwrap();
+        assert!(reloaded.keep_restart_only(&running).is_empty());
+        assert_eq!(reloaded.queue_max_priority.verified, Priority::Urgent);
+        assert_eq!(reloaded.queue_max_priority.untrusted, Priority::Normal);
+        assert!(running.diff(&reloaded)[0].starts_with("queue_max_priority"));
+
+        assert!(from("[queue.max_priority]\nverified = \"asap\"", &[]).is_err());
+    }
+
+    #[test]
+    fn callback_settings() {
+        let config = from(
+            "[callbacks]\nsecret = \"file\"\nmax_attempts = 3\nretry_base = 2",
+            &[("BRIDGE_ECHO_CALLBACK_SECRET", "env")],
+        )
+        .unwrap();
+        assert_eq!(config.callback_secret.as_deref(), Some("env"));
+        assert_eq!(config.callback_max_attempts, 3);
+        assert_eq!(config.callback_retry_base_secs, 2);
+        assert_eq!(config.callback_retry_max_secs, 3600);
+
+        let changes = Config::default().diff(&config);
+        assert!(changes.iter().any(|c| c == "callback_secret: changed"));
+        assert!(from("[callbacks]\nmax_attempts = 0", &[]).is_err());
+
+        let config = from(
+            "[callbacks.files]\nlog = \"/tmp/cb.jsonl\"\n[callbacks.commands]\nsay = [\"espeak\", \"-v\", \"en\"]",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.callback_files["log"], "/tmp/cb.jsonl");
+        assert_eq!(config.callback_commands["say"][0], "espeak");
+        assert!(from("[callbacks.commands]\nsay = []", &[]).is_err());
+    }
+
+    #[test]
+    fn routes_replace_the_default() {
+        assert_eq!(Config::default().routes, routing::defaults());
+        let config = from(
+            "[[routes]]\nchannels = [\"reflection\"]\nto = [{ type = \"discord\", channel_id = \"9\" }]",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.routes.len(), 1);
+        assert_eq!(config.routes[0].to[0].channel_id.as_deref(), Some("9"));
+        assert!(from("[[routes]]\nto = [{ type = \"pager\" }]", &[]).is_err());
+    }
+
+    #[test]
+    fn invalid_values_rejected() {
+        assert!(from("workers = 0", &[]).is_err());
+        let err = from("[injection]\npatterns = [\"(\"]", &[]).unwrap_err();
+        assert!(err.starts_with("injection.patterns[0]"), "{err}");
     }
 }
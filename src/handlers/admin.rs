@@ -0,0 +1,182 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::approval;
+use crate::metrics::METRICS;
+use crate::queue::{self, QueuedRequest};
+use crate::state::AppState;
+use crate::tracker::Outcome;
+
+/// Reply to a queued request an admin removed.
+const REMOVED_RESPONSE: &str = "This message was removed from the queue before it ran.";
+
+/// POST /admin/reload — Re-read the config file and environment.
+///
+/// Same as sending SIGHUP, but reports what changed. In-flight requests
+/// keep the settings they started with.
+pub async fn reload(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    info!("Reload requested via admin endpoint");
+    match state.settings.reload() {
+        Ok(changes) => (
+            StatusCode::OK,
+            Json(json!({"status": "ok", "changes": changes})),
+        ),
+        Err(e) => {
+            warn!("Reload failed, keeping current configuration: {e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"status": "error", "error": e})),
+            )
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct RejectRequest {
+    /// Passed on to the original caller.
+    pub reason: Option<String>,
+}
+
+/// GET /admin/held — Requests waiting for approval, oldest first.
+pub async fn held(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({"held": state.tracker.held_snapshot().await}))
+}
+
+/// POST /admin/held/{id}/approve — Queue a held request to run.
+pub async fn approve(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<Value>) {
+    if approval::approve(&state.queue, &state.tracker, id).await {
+        (
+            StatusCode::OK,
+            Json(json!({"id": id, "status": "approved"})),
+        )
+    } else {
+        not_held(id)
+    }
+}
+
+/// POST /admin/held/{id}/reject — Answer a held request with a rejection
+/// instead of running it. An optional `reason` is appended to the reply.
+pub async fn reject(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    body: Option<Json<RejectRequest>>,
+) -> (StatusCode, Json<Value>) {
+    let reason = body.and_then(|Json(b)| b.reason);
+    let response = match &reason {
+        Some(reason) => format!("{} Reason: {reason}", approval::REJECTED_RESPONSE),
+        None => approval::REJECTED_RESPONSE.to_string(),
+    };
+    if approval::reject(
+        &state.queue,
+        &state.tracker,
+        state.audit.as_ref(),
+        id,
+        &response,
+    )
+    .await
+    {
+        METRICS.approvals.inc("rejected");
+        info!("Held request #{id} rejected");
+        (
+            StatusCode::OK,
+            Json(json!({"id": id, "status": "rejected"})),
+        )
+    } else {
+        not_held(id)
+    }
+}
+
+/// DELETE /admin/queue/{id} — Answer a waiting request with a removal
+/// notice instead of running it.
+pub async fn remove(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<Value>) {
+    let Some(req) = state.queue.remove(id).await else {
+        return not_queued(id);
+    };
+    state.tracker.unhold(id).await;
+    info!("[{}] Request #{id} removed from the queue", req.channel);
+    settle_removed(&state, req).await;
+    (StatusCode::OK, Json(json!({"id": id, "status": "removed"})))
+}
+
+/// POST /admin/queue/{id}/front — Run a waiting request next.
+pub async fn front(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<Value>) {
+    if state.queue.move_to_front(id).await {
+        info!("Request #{id} moved to the front of the queue");
+        (StatusCode::OK, Json(json!({"id": id, "status": "moved"})))
+    } else {
+        not_queued(id)
+    }
+}
+
+/// POST /admin/queue/channels/{channel}/drain — Remove every waiting
+/// request on a channel.
+pub async fn drain(State(state): State<AppState>, Path(channel): Path<String>) -> Json<Value> {
+    let drained = state.queue.drain_channel(&channel).await;
+    let ids: Vec<u64> = drained.iter().map(|r| r.id).collect();
+    info!("[{channel}] Drained {} queued request(s)", ids.len());
+    for req in drained {
+        settle_removed(&state, req).await;
+    }
+    Json(json!({"channel": channel, "drained": ids}))
+}
+
+async fn settle_removed(state: &AppState, req: QueuedRequest) {
+    let (tracker, audit) = (&state.tracker, state.audit.as_ref());
+    queue::settle(req, REMOVED_RESPONSE, Outcome::Cancelled, tracker, audit).await;
+}
+
+/// GET /admin/callbacks — Dead-lettered webhook callbacks, with the count
+/// still being retried.
+pub async fn callbacks(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "pending": state.webhooks.pending_len().await,
+        "dead": state.webhooks.dead_letters().await,
+    }))
+}
+
+/// POST /admin/callbacks/{id}/replay — Retry a dead-lettered callback.
+pub async fn replay_callback(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<Value>) {
+    if state.webhooks.replay(id).await {
+        METRICS.callbacks.inc("replayed");
+        info!("Callback #{id} replayed");
+        (
+            StatusCode::OK,
+            Json(json!({"id": id, "status": "replayed"})),
+        )
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("callback {id} is not dead-lettered")})),
+        )
+    }
+}
+
+fn not_queued(id: u64) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": format!("request {id} is not queued")})),
+    )
+}
+
+fn not_held(id: u64) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": format!("request {id} is not held")})),
+    )
+}
@@ -1,13 +1,30 @@
 use axum::extract::State;
-use axum::http::StatusCode;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use futures_util::stream;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use tokio::sync::{mpsc, oneshot};
 use tracing::{info, warn};
 
+use crate::approval;
+use crate::auth::{self, Caller};
+use crate::callbacks;
+use crate::claude::StreamEvent;
+use crate::injection::{Action, Detection};
+use crate::metrics::METRICS;
 use crate::prompt;
-use crate::queue::QueuedRequest;
+use crate::queue::{self, Priority, QueuedRequest, Reply};
+use crate::ratelimit::Denied;
 use crate::state::AppState;
+use crate::tracker::{HeldRequest, Outcome};
+use crate::trust::TrustLevel;
+
+/// Reply to a request refused by the injection policy.
+const BLOCKED_RESPONSE: &str =
+    "This message was blocked because it looks like a prompt injection attempt.";
 
 #[derive(Deserialize)]
 pub struct ChatRequest {
@@ -16,6 +33,8 @@ pub struct ChatRequest {
     pub sender: Option<String>,
     pub metadata: Option<RequestMetadata>,
     pub callback: Option<CallbackConfig>,
+    /// Requested scheduling class, capped by the channel's trust level.
+    pub priority: Option<Priority>,
 }
 
 #[derive(Deserialize, Clone, Default)]
@@ -28,93 +47,366 @@ pub struct RequestMetadata {
 
 #[derive(Deserialize, Clone)]
 pub struct CallbackConfig {
+    /// One of the types in [`callbacks`]; checked at submission.
     #[serde(rename = "type")]
     pub callback_type: String,
     pub url: Option<String>,
+    /// Configured file or command for `file` and `exec` callbacks.
+    pub target: Option<String>,
 }
 
+/// POST /chat — Run a message through Claude and return the full response.
+///
+/// Clients sending `Accept: text/event-stream` get the same stream as
+/// `/chat/stream` instead.
 pub async fn chat(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<ChatRequest>,
+) -> Response {
+    if wants_event_stream(&headers) {
+        return chat_stream(State(state), headers, Json(body)).await;
+    }
+
+    let Submitted {
+        channel,
+        reply,
+        injection,
+        ..
+    } = match submit(&state, &headers, body, None).await {
+        Ok(submitted) => submitted,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match reply.await {
+        Ok(Reply {
+            text: response_text,
+            ..
+        }) => {
+            let resp_truncated = truncate_str(&response_text, 120);
+            info!("[{channel}] Response: {resp_truncated}");
+
+            let body = with_injection(json!({"response": response_text}), &injection);
+            (StatusCode::OK, Json(body)).into_response()
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"response": "Worker dropped the request"})),
+        )
+            .into_response(),
+    }
+}
+
+/// POST /chat/stream — Run a message through Claude, streaming Server-Sent
+/// Events: `text` and `tool_use` while Claude works, then one `result`.
+pub async fn chat_stream(
+    State(state): State<AppState>,
+    headers: HeaderMap,
     Json(body): Json<ChatRequest>,
-) -> (StatusCode, Json<Value>) {
+) -> Response {
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let Submitted {
+        channel,
+        reply,
+        injection,
+        ..
+    } = match submit(&state, &headers, body, Some(events_tx)).await {
+        Ok(submitted) => submitted,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let stream = stream::unfold(Some((events_rx, reply)), move |pending| {
+        let channel = channel.clone();
+        let injection = injection.clone();
+        async move {
+            let (mut events, rx) = pending?;
+            match events.recv().await {
+                Some(event) => Some((sse_event(event), Some((events, rx)))),
+                None => {
+                    let event = match rx.await {
+                        Ok(Reply {
+                            text: response_text,
+                            ..
+                        }) => {
+                            let resp_truncated = truncate_str(&response_text, 120);
+                            info!("[{channel}] Streamed response: {resp_truncated}");
+                            Event::default().event("result").json_data(with_injection(
+                                json!({"response": response_text}),
+                                &injection,
+                            ))
+                        }
+                        Err(_) => Event::default()
+                            .event("error")
+                            .json_data(json!({"response": "Worker dropped the request"})),
+                    };
+                    Some((event, None))
+                }
+            }
+        }
+    });
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn sse_event(event: StreamEvent) -> Result<Event, axum::Error> {
+    match event {
+        StreamEvent::Text(text) => Event::default()
+            .event("text")
+            .json_data(json!({"text": text})),
+        StreamEvent::ToolUse { name, input } => Event::default()
+            .event("tool_use")
+            .json_data(json!({"name": name, "input": input})),
+    }
+}
+
+fn wants_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// A request that has been accepted onto the queue.
+pub struct Submitted {
+    pub id: u64,
+    pub channel: String,
+    pub reply: oneshot::Receiver<Reply>,
+    /// Kills the Claude subprocess once the request is running.
+    pub cancel: oneshot::Sender<()>,
+    pub injection: Detection,
+}
+
+/// Add the injection scan to a response body when anything matched.
+pub fn with_injection(mut body: Value, injection: &Detection) -> Value {
+    if injection.is_detected() {
+        body["injection"] = json!(injection);
+    }
+    body
+}
+
+/// Authenticate and validate a chat request, build its prompt and enqueue
+/// it. The channel, and so the trust level, must be one the caller's token
+/// allows; without tokens configured, trusted channels are refused.
+pub async fn submit(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: ChatRequest,
+    events: Option<mpsc::UnboundedSender<StreamEvent>>,
+) -> Result<Submitted, Response> {
+    let settings = state.settings.load();
+    let caller =
+        auth::api_caller(&settings.config.auth, headers).map_err(IntoResponse::into_response)?;
+
     let message = match body.message.as_deref().map(str::trim) {
         Some(m) if !m.is_empty() => m.to_string(),
         _ => {
-            return (
+            return Err((
                 StatusCode::BAD_REQUEST,
                 Json(json!({"response": "Missing message"})),
             )
+                .into_response())
         }
     };
 
-    let channel = body.channel.unwrap_or_else(|| "discord".into());
+    let channel = body
+        .channel
+        .or_else(|| caller.default_channel().map(String::from))
+        .unwrap_or_else(|| "discord".into());
+    if !caller.allows(&channel, settings.config.channels.trust(&channel)) {
+        match &caller {
+            Caller::Token(t) => warn!(
+                "[{channel}] Rejected request from {}: channel not allowed",
+                t.label()
+            ),
+            Caller::Open => {
+                warn!("[{channel}] Rejected request without a token: channel is trusted")
+            }
+        }
+        return Err(auth::forbidden_channel(&caller, &channel).into_response());
+    }
+
+    let sender = body.sender.unwrap_or_else(|| {
+        settings
+            .config
+            .channels
+            .default_sender(&channel)
+            .unwrap_or("unknown")
+            .to_string()
+    });
+
+    let metadata = body.metadata.unwrap_or_default();
+    let callback = body.callback;
+    if let Some(cb) = &callback {
+        if let Err(e) = callbacks::validate(cb, &metadata, &settings.config) {
+            warn!("[{channel}] Rejected request: {e}");
+            return Err((StatusCode::BAD_REQUEST, Json(json!({"response": e}))).into_response());
+        }
+    }
+
+    let mut level = settings.config.channels.trust(&channel);
+    // Trusted channels are never counted, so there is nothing to refund.
+    let limited = level != TrustLevel::Trusted;
+    let refund = || {
+        if limited {
+            let limits = &settings.config.limits;
+            state.limiter.refund(limits, &channel, &sender);
+        }
+    };
+    if limited {
+        if let Err(denied) = state
+            .limiter
+            .check(&settings.config.limits, &channel, &sender)
+        {
+            warn!("[{channel}] sender={sender} Rejected: {}", denied.message());
+            let kind = if denied.quota { "quota" } else { "rate" };
+            METRICS
+                .rate_limited
+                .inc(&format!("{}_{kind}", denied.scope));
+            return Err(too_many_requests(&denied));
+        }
+    }
 
     let truncated = truncate_str(&message, 120);
     info!("[{channel}] Received: {truncated}");
 
-    if state.detector.detect(&message) {
-        warn!("[{channel}] INJECTION DETECTED in message");
+    let mut injection = settings.detector.scan(&message);
+    injection.action = settings.config.injection_policy.decide(level, &injection);
+    if let Some(action) = injection.action {
+        warn!(
+            "[{channel}] INJECTION DETECTED in message: severity={} score={} categories=[{}] patterns=[{}] action={action}",
+            injection.severity,
+            injection.score,
+            injection.describe_categories(),
+            injection.patterns.join(", ")
+        );
+        for id in &injection.patterns {
+            METRICS.injection_detections.inc(id);
+        }
+        METRICS.injection_actions.inc(&action.to_string());
     }
 
-    let sender = body.sender.unwrap_or_else(|| match channel.as_str() {
-        "discord" | "discord-echo" => "D".into(),
-        "voice" => "D".into(),
-        _ => "unknown".into(),
-    });
-    let metadata = body.metadata.unwrap_or_default();
-    let callback = body.callback;
+    if injection.action == Some(Action::Downgrade) {
+        level = TrustLevel::Untrusted;
+    }
+    let hold_reason = match injection.action {
+        Some(Action::Quarantine) => Some(format!(
+            "injection: {} ({})",
+            injection.severity,
+            injection.describe_categories()
+        )),
+        Some(Action::Warn) | None
+            if level != TrustLevel::Trusted && settings.tool_requests.is_match(&message) =>
+        {
+            Some("tool request".to_string())
+        }
+        _ => None,
+    };
 
-    let mut final_prompt = prompt::build(&message, &channel, &state.detector);
+    let mut final_prompt = prompt::build(&message, &channel, level, &injection);
 
     if let Some(ctx) = &metadata.context {
         final_prompt = format!("{final_prompt}\n\n[Context: {ctx}]");
     }
 
-    let (tx, rx) = tokio::sync::oneshot::channel();
+    let id = state.tracker.next_id().await;
+    let (tx, rx) = oneshot::channel();
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+
+    let priority = settings.config.queue_max_priority.cap(level, body.priority);
+    if body.priority.is_some_and(|p| p > priority) {
+        info!("[{channel}] Priority capped to {priority} for {level:?} channel");
+    }
 
     // Check for cross-channel conversation: if the same sender has an active
     // request on a different channel, priority-enqueue so it processes next.
-    let priority = state
+    let merge = state
         .tracker
         .has_active_on_other_channel(&sender, &channel)
         .await;
 
     let queued = QueuedRequest {
+        id,
         channel: channel.clone(),
-        sender,
+        session_key: state.sessions.key(&channel, &sender),
+        trust: level,
+        injection: injection.clone(),
+        priority,
+        sender: sender.clone(),
         metadata,
         callback,
         prompt: final_prompt,
         original_message: message,
         respond: tx,
+        events,
+        cancel: cancel_rx,
     };
 
-    if priority {
-        state.queue.send_priority(queued).await;
+    if injection.action == Some(Action::Block) {
+        refund();
+        let (tracker, audit) = (&state.tracker, state.audit.as_ref());
+        queue::settle(queued, BLOCKED_RESPONSE, Outcome::Blocked, tracker, audit).await;
+    } else if let Some(reason) = hold_reason {
+        let held = HeldRequest::new(&queued, reason);
+        warn!(
+            "[{channel}] Request #{id} held for approval: {}",
+            held.reason
+        );
+        METRICS.approvals.inc("held");
+        // Queue first: approving looks the request up there.
+        state.queue.hold(queued).await;
+        state.tracker.hold(held.clone()).await;
+        tokio::spawn(async move { approval::notify(&settings, &held).await });
     } else {
-        state.queue.send(queued).await;
+        let sent = if merge {
+            state.queue.send_priority(queued).await
+        } else {
+            state.queue.send(queued).await
+        };
+        if sent.is_err() {
+            warn!("[{channel}] Rejected request #{id}: queue is full");
+            METRICS.queue_rejections.inc();
+            refund();
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({"response": "Queue is full, try again later"})),
+            )
+                .into_response());
+        }
     }
 
-    match rx.await {
-        Ok(response_text) => {
-            let resp_truncated = truncate_str(&response_text, 120);
-            info!("[{channel}] Response: {resp_truncated}");
+    Ok(Submitted {
+        id,
+        channel,
+        reply: rx,
+        cancel: cancel_tx,
+        injection,
+    })
+}
 
-            (StatusCode::OK, Json(json!({"response": response_text})))
-        }
-        Err(_) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"response": "Worker dropped the request"})),
-        ),
-    }
+/// 429 with `Retry-After` for a request over its rate limit or quota.
+fn too_many_requests(denied: &Denied) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, denied.retry_after.to_string())],
+        Json(json!({
+            "response": denied.message(),
+            "retry_after": denied.retry_after,
+        })),
+    )
+        .into_response()
 }
 
 /// Truncate a string to at most `max_bytes` bytes at a char boundary.
-fn truncate_str(s: &str, max_bytes: usize) -> String {
+pub fn truncate_str(s: &str, max_bytes: usize) -> String {
     if s.len() <= max_bytes {
         s.to_string()
     } else {
-        let end = s.floor_char_boundary(max_bytes);
+        let mut end = max_bytes;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
         format!("{}...", &s[..end])
     }
 }
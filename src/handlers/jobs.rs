@@ -0,0 +1,215 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::audit::AuditLog;
+use crate::auth;
+use crate::handlers::chat::{self, ChatRequest, Submitted};
+use crate::jobs::{self, CancelResult, JobStatus, JobStore};
+use crate::queue::{self, Queue};
+use crate::state::AppState;
+use crate::tracker::{Outcome, RequestTracker};
+
+/// POST /jobs — Enqueue a chat request and return its id immediately.
+///
+/// Takes the same body as `/chat`. Poll `GET /jobs/{id}` for the result.
+pub async fn submit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<ChatRequest>,
+) -> Response {
+    let Submitted {
+        id,
+        channel,
+        reply,
+        cancel,
+        injection,
+    } = match chat::submit(&state, &headers, body, None).await {
+        Ok(submitted) => submitted,
+        Err(rejection) => return rejection,
+    };
+
+    state.jobs.insert(id, &channel, cancel).await;
+    info!("[{channel}] Job #{id} submitted");
+
+    let jobs = state.jobs.clone();
+    tokio::spawn(async move {
+        match reply.await {
+            Ok(reply) => jobs.finish(id, Some(reply.outcome), reply.text).await,
+            Err(_) => {
+                jobs.finish(id, None, "Worker dropped the request".into())
+                    .await
+            }
+        }
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        Json(chat::with_injection(
+            json!({"id": id, "status": "queued"}),
+            &injection,
+        )),
+    )
+        .into_response()
+}
+
+/// GET /jobs/{id} — Status and, once finished, the result of a job.
+pub async fn get(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<Value>) {
+    let caller = match auth::api_caller(&state.settings.load().config.auth, &headers) {
+        Ok(caller) => caller,
+        Err(rejection) => return rejection,
+    };
+    let live = if state.tracker.is_active(id).await {
+        JobStatus::Running
+    } else if state.tracker.is_held(id).await {
+        JobStatus::Held
+    } else {
+        JobStatus::Queued
+    };
+    let channels = &state.settings.load().config.channels;
+    match state.jobs.get(id, live).await {
+        // Jobs on channels the caller cannot use are reported as missing.
+        Some(job) if caller.allows(&job.channel, channels.trust(&job.channel)) => {
+            (StatusCode::OK, Json(json!(job)))
+        }
+        _ => not_found(id),
+    }
+}
+
+/// DELETE /jobs/{id} — Cancel a job. A queued job is removed from the queue;
+/// a running one has its Claude subprocess killed.
+pub async fn cancel(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<Value>) {
+    let caller = match auth::api_caller(&state.settings.load().config.auth, &headers) {
+        Ok(caller) => caller,
+        Err(rejection) => return rejection,
+    };
+    let channels = &state.settings.load().config.channels;
+    match state.jobs.channel(id).await {
+        Some(channel) if caller.allows(&channel, channels.trust(&channel)) => {}
+        _ => return not_found(id),
+    }
+
+    let (jobs, queue, tracker) = (&state.jobs, &state.queue, &state.tracker);
+    match withdraw(jobs, queue, tracker, state.audit.as_ref(), id).await {
+        CancelResult::Cancelled => (
+            StatusCode::OK,
+            Json(json!({"id": id, "status": "cancelled"})),
+        ),
+        CancelResult::AlreadyFinished => (
+            StatusCode::CONFLICT,
+            Json(json!({"error": format!("job {id} has already finished")})),
+        ),
+        CancelResult::NotFound => not_found(id),
+    }
+}
+
+/// Cancel a job: a waiting request is taken out of the queue and settled
+/// as cancelled, a running one has its subprocess killed.
+async fn withdraw(
+    jobs: &JobStore,
+    queue: &Queue,
+    tracker: &RequestTracker,
+    audit: Option<&AuditLog>,
+    id: u64,
+) -> CancelResult {
+    let result = jobs.cancel(id).await;
+    if !matches!(result, CancelResult::Cancelled) {
+        return result;
+    }
+    match queue.remove(id).await {
+        Some(req) => {
+            tracker.unhold(id).await;
+            info!("[{}] Job #{id} cancelled (was queued)", req.channel);
+            queue::settle(
+                req,
+                jobs::CANCELLED_RESPONSE,
+                Outcome::Cancelled,
+                tracker,
+                audit,
+            )
+            .await;
+        }
+        None => {
+            info!("Job #{id} cancelled (was running)");
+            jobs.kill(id).await;
+        }
+    }
+    result
+}
+
+fn not_found(id: u64) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": format!("job {id} not found")})),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::handlers::chat::RequestMetadata;
+    use crate::injection::Detection;
+    use crate::queue::{Priority, QueueOptions, QueuedRequest};
+    use crate::trust::TrustLevel;
+
+    #[tokio::test]
+    async fn cancelling_a_queued_job_settles_it() {
+        let (jobs, queue, tracker) = (
+            JobStore::new(),
+            Queue::new(QueueOptions::default()),
+            RequestTracker::new(None),
+        );
+        let id = tracker.next_id().await;
+        let (respond, reply) = oneshot::channel();
+        let (kill, cancel) = oneshot::channel();
+        jobs.insert(id, "discord", kill).await;
+        queue
+            .send(QueuedRequest {
+                id,
+                channel: "discord".into(),
+                sender: "D".into(),
+                session_key: "discord".into(),
+                trust: TrustLevel::Verified,
+                injection: Detection::default(),
+                priority: Priority::Normal,
+                metadata: RequestMetadata::default(),
+                callback: None,
+                prompt: "hello".into(),
+                original_message: "hello".into(),
+                respond,
+                events: None,
+                cancel,
+            })
+            .await
+            .unwrap();
+
+        let result = withdraw(&jobs, &queue, &tracker, None, id).await;
+        assert!(matches!(result, CancelResult::Cancelled));
+        assert_eq!(queue.len().await, 0);
+
+        // What the watcher spawned by `submit` would record.
+        let reply = reply.await.unwrap();
+        assert_eq!(reply.outcome, Outcome::Cancelled);
+        jobs.finish(id, Some(reply.outcome), reply.text).await;
+        let job = jobs.get(id, JobStatus::Queued).await.unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+
+        let history = tracker.completed_snapshot().await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, id);
+        assert_eq!(history[0].outcome, Outcome::Cancelled);
+    }
+}
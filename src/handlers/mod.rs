@@ -1,5 +1,7 @@
+pub mod admin;
 pub mod call_ended;
 pub mod chat;
 pub mod health;
+pub mod jobs;
 pub mod monitor;
 pub mod session_started;
@@ -1,15 +1,44 @@
 use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
 use axum::Json;
 use serde_json::json;
 
+use crate::metrics::METRICS;
 use crate::state::AppState;
 
 pub async fn status(State(state): State<AppState>) -> Json<serde_json::Value> {
     let active = state.tracker.active_snapshot().await;
+    let queued = state.queue.snapshot().await;
+    let held = state.tracker.held_snapshot().await;
     let completed = state.tracker.completed_snapshot().await;
+    let limits = state.limiter.snapshot(&state.settings.load().config.limits);
 
     Json(json!({
         "active": active,
+        "queued": queued,
+        "held": held,
         "completed": completed,
+        "limits": limits,
     }))
 }
+
+/// GET /api/queue — Waiting requests in their expected run order.
+pub async fn queue(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(json!({"queued": state.queue.snapshot().await}))
+}
+
+/// GET /api/voice-sessions — Voice calls that have not expired, oldest
+/// first.
+pub async fn voice_sessions(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(json!({"calls": state.voice_sessions.active().await}))
+}
+
+/// GET /metrics — Prometheus text exposition.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let depth = state.queue.len().await;
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(depth),
+    )
+}
@@ -11,7 +11,6 @@ use crate::state::AppState;
 pub struct SessionStartedRequest {
     pub call_sid: String,
     pub sender: String,
-    #[allow(dead_code)]
     pub transport: String,
 }
 
@@ -31,7 +30,7 @@ pub async fn session_started(
     );
     state
         .voice_sessions
-        .touch(&body.sender, &body.call_sid)
+        .touch(&body.sender, &body.call_sid, Some(&body.transport))
         .await;
     (StatusCode::OK, Json(json!({"status": "ok"})))
 }
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::{oneshot, RwLock};
+
+use crate::store;
+use crate::tracker::Outcome;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    /// Waiting for an admin to approve it.
+    Held,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+    Blocked,
+    Rejected,
+}
+
+impl From<Outcome> for JobStatus {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Completed => Self::Done,
+            Outcome::Failed | Outcome::TimedOut => Self::Failed,
+            Outcome::Cancelled => Self::Cancelled,
+            Outcome::Blocked => Self::Blocked,
+            Outcome::Rejected => Self::Rejected,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct JobSnapshot {
+    pub id: u64,
+    pub channel: String,
+    pub status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    pub submitted_unix: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_unix: Option<u64>,
+}
+
+struct Job {
+    channel: String,
+    submitted_unix: u64,
+    /// Set once the job has finished (or was cancelled before running).
+    finished: Option<Finished>,
+    /// Kills the running subprocess. Taken on first use.
+    cancel: Option<oneshot::Sender<()>>,
+}
+
+struct Finished {
+    status: JobStatus,
+    outcome: Option<Outcome>,
+    result: String,
+    unix: u64,
+}
+
+/// Result of a cancel request.
+pub enum CancelResult {
+    /// The job is unknown (never existed or already evicted).
+    NotFound,
+    /// The job had already finished; nothing to cancel.
+    AlreadyFinished,
+    /// The job was pending and is now cancelled.
+    Cancelled,
+}
+
+/// Result recorded for a cancelled job.
+pub const CANCELLED_RESPONSE: &str = "Request was cancelled.";
+
+const MAX_FINISHED: usize = 500;
+
+/// Asynchronous jobs submitted through `POST /jobs`, keyed by the request id
+/// the tracker assigned. Whether an unfinished job is queued or running is
+/// derived from the tracker, so this only holds submission and result data.
+#[derive(Clone)]
+pub struct JobStore {
+    inner: Arc<RwLock<HashMap<u64, Job>>>,
+}
+
+impl JobStore {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub async fn insert(&self, id: u64, channel: &str, cancel: oneshot::Sender<()>) {
+        let mut jobs = self.inner.write().await;
+        jobs.insert(
+            id,
+            Job {
+                channel: channel.to_string(),
+                submitted_unix: store::now_unix(),
+                finished: None,
+                cancel: Some(cancel),
+            },
+        );
+    }
+
+    /// Record the final result. A job already marked cancelled keeps that
+    /// status.
+    pub async fn finish(&self, id: u64, outcome: Option<Outcome>, result: String) {
+        let mut jobs = self.inner.write().await;
+        if let Some(job) = jobs.get_mut(&id) {
+            if job.finished.is_none() {
+                job.finished = Some(Finished {
+                    status: outcome.map_or(JobStatus::Failed, JobStatus::from),
+                    outcome,
+                    result,
+                    unix: store::now_unix(),
+                });
+                job.cancel = None;
+            }
+        }
+        evict_finished(&mut jobs);
+    }
+
+    /// Mark a job cancelled. Done before the request is pulled out of the
+    /// queue or killed, so the result the worker side reports afterwards
+    /// cannot overwrite it.
+    pub async fn cancel(&self, id: u64) -> CancelResult {
+        let mut jobs = self.inner.write().await;
+        let Some(job) = jobs.get_mut(&id) else {
+            return CancelResult::NotFound;
+        };
+        if job.finished.is_some() {
+            return CancelResult::AlreadyFinished;
+        }
+        job.finished = Some(Finished {
+            status: JobStatus::Cancelled,
+            outcome: Some(Outcome::Cancelled),
+            result: CANCELLED_RESPONSE.into(),
+            unix: store::now_unix(),
+        });
+        CancelResult::Cancelled
+    }
+
+    /// Signal the worker to kill the job's running subprocess.
+    pub async fn kill(&self, id: u64) {
+        let mut jobs = self.inner.write().await;
+        if let Some(tx) = jobs.get_mut(&id).and_then(|j| j.cancel.take()) {
+            let _ = tx.send(());
+        }
+    }
+
+    /// Snapshot of one job. `live` is the status to report while it is
+    /// unfinished (queued, held or running), which the tracker knows.
+    pub async fn get(&self, id: u64, live: JobStatus) -> Option<JobSnapshot> {
+        let jobs = self.inner.read().await;
+        let job = jobs.get(&id)?;
+        Some(match &job.finished {
+            Some(f) => JobSnapshot {
+                id,
+                channel: job.channel.clone(),
+                status: f.status,
+                outcome: f.outcome,
+                result: Some(f.result.clone()),
+                submitted_unix: job.submitted_unix,
+                finished_unix: Some(f.unix),
+            },
+            None => JobSnapshot {
+                id,
+                channel: job.channel.clone(),
+                status: live,
+                outcome: None,
+                result: None,
+                submitted_unix: job.submitted_unix,
+                finished_unix: None,
+            },
+        })
+    }
+
+    /// Channel the job was submitted on, if it exists.
+    pub async fn channel(&self, id: u64) -> Option<String> {
+        self.inner.read().await.get(&id).map(|j| j.channel.clone())
+    }
+}
+
+/// Drop the oldest finished jobs once more than `MAX_FINISHED` are kept.
+fn evict_finished(jobs: &mut HashMap<u64, Job>) {
+    let mut finished: Vec<(u64, u64)> = jobs
+        .iter()
+        .filter_map(|(id, j)| j.finished.as_ref().map(|f| (f.unix, *id)))
+        .collect();
+    if finished.len() <= MAX_FINISHED {
+        return;
+    }
+    finished.sort_unstable();
+    let excess = finished.len() - MAX_FINISHED;
+    for (_, id) in finished.into_iter().take(excess) {
+        jobs.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lifecycle() {
+        let jobs = JobStore::new();
+        let (tx, _rx) = oneshot::channel();
+        jobs.insert(7, "discord", tx).await;
+
+        let queued = jobs.get(7, JobStatus::Queued).await.unwrap();
+        assert_eq!(queued.status, JobStatus::Queued);
+        assert_eq!(
+            jobs.get(7, JobStatus::Running).await.unwrap().status,
+            JobStatus::Running
+        );
+
+        jobs.finish(7, Some(Outcome::Completed), "hi".into()).await;
+        let done = jobs.get(7, JobStatus::Queued).await.unwrap();
+        assert_eq!(done.status, JobStatus::Done);
+        assert_eq!(done.result.as_deref(), Some("hi"));
+    }
+
+    #[tokio::test]
+    async fn timeout_is_failure() {
+        let jobs = JobStore::new();
+        let (tx, _rx) = oneshot::channel();
+        jobs.insert(1, "discord", tx).await;
+        jobs.finish(1, Some(Outcome::TimedOut), "slow".into()).await;
+        assert_eq!(
+            jobs.get(1, JobStatus::Queued).await.unwrap().status,
+            JobStatus::Failed
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_signals_running_job() {
+        let jobs = JobStore::new();
+        let (tx, rx) = oneshot::channel();
+        jobs.insert(3, "discord", tx).await;
+
+        assert!(matches!(jobs.cancel(3).await, CancelResult::Cancelled));
+        jobs.kill(3).await;
+        assert!(rx.await.is_ok());
+
+        // The worker's own result must not overwrite the cancellation.
+        jobs.finish(3, Some(Outcome::Completed), "late".into())
+            .await;
+        let job = jobs.get(3, JobStatus::Queued).await.unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+
+        assert!(matches!(
+            jobs.cancel(3).await,
+            CancelResult::AlreadyFinished
+        ));
+        assert!(matches!(jobs.cancel(99).await, CancelResult::NotFound));
+    }
+}
@@ -1,55 +1,115 @@
 mod alerts;
+mod approval;
+mod audit;
+mod auth;
+mod callbacks;
 mod claude;
 mod config;
 mod handlers;
 mod injection;
+mod jobs;
+mod metrics;
 mod monitor_cli;
+mod normalize;
 mod prompt;
 mod queue;
+mod ratelimit;
 mod router;
+mod routing;
+mod session;
+mod settings;
+mod speech;
 mod state;
+mod store;
 mod tracker;
 mod trust;
 mod voice_session;
+mod webhook;
 
 use config::Config;
+use settings::SharedSettings;
 use state::AppState;
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
+const USAGE: &str = "usage: bridge-echo [serve|monitor [--once]|verify-audit] [--config <path>]";
+
 #[tokio::main]
 async fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let command = args.get(1).map(|s| s.as_str());
+    let mut command = None;
+    let mut once = false;
+    let mut config_path = std::env::var("BRIDGE_ECHO_CONFIG").ok();
 
-    match command {
-        Some("monitor") => {
-            let once = args.iter().any(|a| a == "--once");
-            monitor_cli::run(once).await;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--once" => once = true,
+            "--config" => match args.next() {
+                Some(path) => config_path = Some(path),
+                None => exit_usage("--config requires a path"),
+            },
+            _ if command.is_none() => command = Some(arg),
+            other => exit_usage(&format!("unexpected argument: {other}")),
+        }
+    }
+
+    let config = match Config::load(config_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {e}");
+            std::process::exit(1);
         }
-        Some("serve") | None => serve().await,
-        Some(other) => {
-            eprintln!("unknown command: {other}");
-            eprintln!("usage: bridge-echo [serve|monitor [--once]]");
+    };
+
+    match command.as_deref() {
+        Some("monitor") => monitor_cli::run(config.port, config.auth.status_token, once).await,
+        Some("verify-audit") => verify_audit(&config),
+        Some("serve") | None => serve(config, config_path).await,
+        Some(other) => exit_usage(&format!("unknown command: {other}")),
+    }
+}
+
+/// Check the audit log's hash chain and exit non-zero if it is broken.
+fn verify_audit(config: &Config) {
+    let Some(dir) = config.audit_path() else {
+        exit_usage("no audit log configured (set audit.dir or data_dir)");
+    };
+    match audit::verify(&dir) {
+        Ok(count) => println!("{}: {count} records, chain intact", dir.display()),
+        Err(e) => {
+            eprintln!("audit log verification failed: {e}");
             std::process::exit(1);
         }
     }
 }
 
-async fn serve() {
+fn exit_usage(msg: &str) -> ! {
+    eprintln!("{msg}");
+    eprintln!("{USAGE}");
+    std::process::exit(1);
+}
+
+async fn serve(config: Config, config_path: Option<String>) {
     tracing_subscriber::fmt()
         .with_env_filter(
             EnvFilter::try_from_default_env().unwrap_or_else(|_| "bridge_echo=info".into()),
         )
         .init();
 
-    let config = Config::from_env().expect("invalid configuration");
     let addr = format!("{}:{}", config.host, config.port);
 
     info!("bridge-echo listening on {addr}");
+    config.auth.warn_unprotected();
 
-    let state = AppState::new(config);
-    alerts::spawn(state.tracker.clone(), &state.config);
+    let state = AppState::new(config, config_path);
+    alerts::spawn(state.tracker.clone(), state.settings.clone());
+    approval::spawn(
+        state.queue.clone(),
+        state.tracker.clone(),
+        state.settings.clone(),
+        state.audit.clone(),
+    );
+    spawn_reload_on_sighup(state.settings.clone());
     let app = router::build(state);
 
     let listener = tokio::net::TcpListener::bind(&addr)
@@ -58,3 +118,29 @@ async fn serve() {
 
     axum::serve(listener, app).await.expect("server error");
 }
+
+/// Reload configuration whenever the process receives SIGHUP.
+#[cfg(unix)]
+fn spawn_reload_on_sighup(settings: SharedSettings) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("failed to install SIGHUP handler: {e}");
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP received, reloading configuration");
+            if let Err(e) = settings.reload() {
+                warn!("Reload failed, keeping current configuration: {e}");
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_reload_on_sighup(_settings: SharedSettings) {}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// Process-wide metrics, updated next to the log lines they mirror and
+/// rendered by `GET /metrics`.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// Request duration buckets, in seconds.
+const DURATION_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Requests pushed to the front of the queue for a cross-channel merge.
+    pub priority_enqueues: Counter,
+    /// Requests refused with 503 because the queue was full.
+    pub queue_rejections: Counter,
+    /// Wall-clock time of each Claude run, by channel.
+    pub request_duration: Histogram,
+    /// Claude subprocess results, by exit code (or `timeout`, `cancelled`,
+    /// `signal`, `spawn_error`).
+    pub claude_exits: LabeledCounter,
+    /// Claude output that was not valid JSON.
+    pub parse_failures: Counter,
+    /// Injection pattern matches, by pattern id.
+    pub injection_detections: LabeledCounter,
+    /// Injection policy decisions, by action.
+    pub injection_actions: LabeledCounter,
+    /// Voice call injections, by result.
+    pub voice_injects: LabeledCounter,
+    /// Claude summaries of responses for voice calls, by result.
+    pub speech_summaries: LabeledCounter,
+    /// Callback deliveries, by result (and type for non-webhooks).
+    pub callbacks: LabeledCounter,
+    /// Responses sent along each routing rule.
+    pub routes: LabeledCounter,
+    /// Discord alerts, by result.
+    pub alerts: LabeledCounter,
+    /// Requests held for approval and what became of them.
+    pub approvals: LabeledCounter,
+    /// Requests refused with 429, by scope and limit.
+    pub rate_limited: LabeledCounter,
+}
+
+impl Metrics {
+    /// Prometheus text exposition. `queue_depth` is sampled by the caller.
+    pub fn render(&self, queue_depth: usize) -> String {
+        let mut out = String::new();
+        gauge(
+            &mut out,
+            "bridge_echo_queue_depth",
+            "Requests waiting for a worker.",
+            queue_depth as u64,
+        );
+        self.priority_enqueues.render(
+            &mut out,
+            "bridge_echo_priority_enqueues_total",
+            "Requests moved to the front of the queue for a cross-channel merge.",
+        );
+        self.queue_rejections.render(
+            &mut out,
+            "bridge_echo_queue_rejections_total",
+            "Requests refused because the queue was full.",
+        );
+        self.request_duration.render(
+            &mut out,
+            "bridge_echo_request_duration_seconds",
+            "Time spent running Claude for a request.",
+            "channel",
+        );
+        self.claude_exits.render(
+            &mut out,
+            "bridge_echo_claude_exits_total",
+            "Claude subprocess results by exit code.",
+            "code",
+        );
+        self.parse_failures.render(
+            &mut out,
+            "bridge_echo_claude_parse_failures_total",
+            "Claude outputs that could not be parsed as JSON.",
+        );
+        self.injection_detections.render(
+            &mut out,
+            "bridge_echo_injection_detections_total",
+            "Injection pattern matches by pattern.",
+            "pattern",
+        );
+        self.injection_actions.render(
+            &mut out,
+            "bridge_echo_injection_actions_total",
+            "Injection policy decisions by action.",
+            "action",
+        );
+        self.voice_injects.render(
+            &mut out,
+            "bridge_echo_voice_injects_total",
+            "Responses routed into active voice calls by result.",
+            "result",
+        );
+        self.speech_summaries.render(
+            &mut out,
+            "bridge_echo_speech_summaries_total",
+            "Claude summaries of long responses for voice calls by result.",
+            "result",
+        );
+        self.callbacks.render(
+            &mut out,
+            "bridge_echo_callbacks_total",
+            "Callback deliveries by result.",
+            "result",
+        );
+        self.alerts.render(
+            &mut out,
+            "bridge_echo_alerts_total",
+            "Discord alerts for long-running requests by result.",
+            "result",
+        );
+        self.approvals.render(
+            &mut out,
+            "bridge_echo_approvals_total",
+            "Requests held for approval by decision.",
+            "decision",
+        );
+        self.rate_limited.render(
+            &mut out,
+            "bridge_echo_rate_limited_total",
+            "Requests refused for exceeding a rate limit or daily quota.",
+            "limit",
+        );
+        self.routes.render(
+            &mut out,
+            "bridge_echo_routes_total",
+            "Responses sent along each routing rule.",
+            "route",
+        );
+        out
+    }
+}
+
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        header(out, name, help, "counter");
+        let _ = writeln!(out, "{name} {}", self.0.load(Ordering::Relaxed));
+    }
+}
+
+/// A counter with a single label.
+#[derive(Default)]
+pub struct LabeledCounter(Mutex<BTreeMap<String, u64>>);
+
+impl LabeledCounter {
+    pub fn inc(&self, label: &str) {
+        let mut values = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        *values.entry(label.to_string()).or_default() += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, label: &str) {
+        header(out, name, help, "counter");
+        let values = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for (value, count) in values.iter() {
+            let _ = writeln!(out, "{name}{{{label}=\"{}\"}} {count}", escape(value));
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct Buckets {
+    /// Cumulative count per entry of `DURATION_BUCKETS`.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// A histogram over `DURATION_BUCKETS` with a single label.
+#[derive(Default)]
+pub struct Histogram(Mutex<BTreeMap<String, Buckets>>);
+
+impl Histogram {
+    pub fn observe(&self, label: &str, value: f64) {
+        let mut series = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let b = series.entry(label.to_string()).or_insert_with(|| Buckets {
+            counts: vec![0; DURATION_BUCKETS.len()],
+            ..Default::default()
+        });
+        for (count, bound) in b.counts.iter_mut().zip(DURATION_BUCKETS) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        b.sum += value;
+        b.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, label: &str) {
+        header(out, name, help, "histogram");
+        let series = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for (value, b) in series.iter() {
+            let value = escape(value);
+            for (count, bound) in b.counts.iter().zip(DURATION_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{{{label}=\"{value}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{label}=\"{value}\",le=\"+Inf\"}} {}",
+                b.count
+            );
+            let _ = writeln!(out, "{name}_sum{{{label}=\"{value}\"}} {}", b.sum);
+            let _ = writeln!(out, "{name}_count{{{label}=\"{value}\"}} {}", b.count);
+        }
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_labels() {
+        let m = Metrics::default();
+        m.priority_enqueues.inc();
+        m.voice_injects.inc("success");
+        m.voice_injects.inc("success");
+        m.callbacks.inc("say \"hi\"");
+        let text = m.render(4);
+        assert!(text.contains("bridge_echo_queue_depth 4\n"));
+        assert!(text.contains("bridge_echo_priority_enqueues_total 1\n"));
+        assert!(text.contains("bridge_echo_voice_injects_total{result=\"success\"} 2\n"));
+        assert!(text.contains("{result=\"say \\\"hi\\\"\"}"));
+        assert!(text.contains("# TYPE bridge_echo_alerts_total counter\n"));
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let m = Metrics::default();
+        m.request_duration.observe("discord", 3.0);
+        m.request_duration.observe("discord", 45.0);
+        let text = m.render(0);
+        let name = "bridge_echo_request_duration_seconds";
+        assert!(text.contains(&format!(
+            "{name}_bucket{{channel=\"discord\",le=\"1\"}} 0\n"
+        )));
+        assert!(text.contains(&format!(
+            "{name}_bucket{{channel=\"discord\",le=\"5\"}} 1\n"
+        )));
+        assert!(text.contains(&format!(
+            "{name}_bucket{{channel=\"discord\",le=\"60\"}} 2\n"
+        )));
+        assert!(text.contains(&format!(
+            "{name}_bucket{{channel=\"discord\",le=\"+Inf\"}} 2\n"
+        )));
+        assert!(text.contains(&format!("{name}_sum{{channel=\"discord\"}} 48\n")));
+    }
+}
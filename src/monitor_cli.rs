@@ -1,5 +1,3 @@
-use std::env;
-
 const RESET: &str = "\x1b[0m";
 const BOLD: &str = "\x1b[1m";
 const DIM: &str = "\x1b[2m";
@@ -11,8 +9,9 @@ const PURPLE: &str = "\x1b[38;5;141m";
 const GRAY: &str = "\x1b[38;5;243m";
 const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
 
-pub async fn run(once: bool) {
-    let port = env::var("BRIDGE_ECHO_PORT").unwrap_or_else(|_| "3100".into());
+/// Poll `/api/status`, sending `token` as a bearer token if the server
+/// requires one.
+pub async fn run(port: u16, token: Option<String>, once: bool) {
     let url = format!("http://127.0.0.1:{port}/api/status");
 
     let client = reqwest::Client::new();
@@ -22,7 +21,12 @@ pub async fn run(once: bool) {
             print!("{CLEAR_SCREEN}");
         }
 
-        match client.get(&url).send().await {
+        let mut request = client.get(&url);
+        if let Some(token) = &token {
+            request = request.bearer_auth(token);
+        }
+
+        match request.send().await {
             Ok(resp) if resp.status().is_success() => {
                 let data: serde_json::Value = match resp.json().await {
                     Ok(v) => v,
@@ -64,6 +68,7 @@ pub async fn run(once: bool) {
 
 fn render(data: &serde_json::Value) {
     let active = data["active"].as_array();
+    let held = data["held"].as_array().filter(|h| !h.is_empty());
     let completed = data["completed"].as_array();
 
     println!("{BOLD}{BLUE}bridge-echo monitor{RESET}");
@@ -84,11 +89,16 @@ fn render(data: &serde_json::Value) {
             let channel = req["channel"].as_str().unwrap_or("?");
             let preview = req["message_preview"].as_str().unwrap_or("");
             let elapsed = req["elapsed_secs"].as_u64().unwrap_or(0);
+            let worker = req["worker"].as_u64().unwrap_or(0);
 
             let elapsed_str = fmt_duration(elapsed);
             let color = if elapsed >= 600 { RED } else { ORANGE };
+            let priority = priority_tag(&req["priority"]);
+            let injection = injection_tag(&req["injection"]);
 
-            println!("  {BOLD}#{id}{RESET}  {PURPLE}{channel}{RESET}  {color}{elapsed_str}{RESET}");
+            println!(
+                "  {BOLD}#{id}{RESET}  {PURPLE}{channel}{RESET}  {color}{elapsed_str}{RESET}  {DIM}worker {worker}{RESET}{priority}{injection}"
+            );
             println!("  {GRAY}{preview}{RESET}");
             println!();
         }
@@ -97,6 +107,74 @@ fn render(data: &serde_json::Value) {
         println!();
     }
 
+    // Requests waiting for a worker
+    if let Some(queued) = data["queued"].as_array().filter(|q| !q.is_empty()) {
+        println!("{BOLD}{GRAY}… {} queued{RESET}", queued.len());
+        println!();
+
+        for req in queued.iter().take(10) {
+            let position = req["position"].as_u64().unwrap_or(0);
+            let id = req["id"].as_u64().unwrap_or(0);
+            let channel = req["channel"].as_str().unwrap_or("?");
+            let sender = req["sender"].as_str().unwrap_or("?");
+            let waited = fmt_duration(req["waited_secs"].as_u64().unwrap_or(0));
+            let priority = priority_tag(&req["priority"]);
+            let preview = req["message_preview"].as_str().unwrap_or("");
+
+            println!(
+                "  {DIM}{position}.{RESET} {BOLD}#{id}{RESET}  {PURPLE}{channel}{RESET}  {DIM}{sender}{RESET}  {GRAY}waiting {waited}{RESET}{priority}"
+            );
+            println!("  {GRAY}{preview}{RESET}");
+            println!();
+        }
+        if queued.len() > 10 {
+            println!("  {DIM}... and {} more{RESET}", queued.len() - 10);
+            println!();
+        }
+    }
+
+    // Requests waiting for approval
+    if let Some(held) = held {
+        println!("{BOLD}{RED}⏸ {} held for approval{RESET}", held.len());
+        println!();
+
+        for req in held {
+            let id = req["id"].as_u64().unwrap_or(0);
+            let channel = req["channel"].as_str().unwrap_or("?");
+            let sender = req["sender"].as_str().unwrap_or("?");
+            let reason = req["reason"].as_str().unwrap_or("");
+            let preview = req["message_preview"].as_str().unwrap_or("");
+            let priority = priority_tag(&req["priority"]);
+
+            println!(
+                "  {BOLD}#{id}{RESET}  {PURPLE}{channel}{RESET}  {DIM}{sender}{RESET}  {ORANGE}{reason}{RESET}{priority}"
+            );
+            println!("  {GRAY}{preview}{RESET}");
+            println!();
+        }
+    }
+
+    // Rate limit and quota usage
+    let limited: Vec<(&str, &str, &serde_json::Value)> = ["channels", "senders"]
+        .into_iter()
+        .flat_map(|scope| {
+            data["limits"][scope]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(move |(key, usage)| (scope, key.as_str(), usage))
+        })
+        .collect();
+    if !limited.is_empty() {
+        println!("{BOLD}{BLUE}◔ usage{RESET}");
+        println!();
+        for (scope, key, usage) in limited {
+            let kind = if scope == "channels" { PURPLE } else { DIM };
+            println!("  {kind}{key}{RESET}  {}", usage_summary(usage));
+        }
+        println!();
+    }
+
     // Completed requests
     let completed_count = completed.map(|c| c.len()).unwrap_or(0);
     println!("{BOLD}{GREEN}✓ {completed_count} completed{RESET} {DIM}(last 50){RESET}");
@@ -110,9 +188,23 @@ fn render(data: &serde_json::Value) {
             let resp = req["response_preview"].as_str().unwrap_or("");
             let duration = req["duration_secs"].as_u64().unwrap_or(0);
 
+            let outcome = req["outcome"].as_str().unwrap_or("completed");
+
             let duration_str = fmt_duration(duration);
+            let (color, tag) = match outcome {
+                "timed_out" => (RED, "  timed out"),
+                "failed" => (RED, "  failed"),
+                "cancelled" => (GRAY, "  cancelled"),
+                "blocked" => (RED, "  blocked"),
+                "rejected" => (ORANGE, "  rejected"),
+                _ => (GREEN, ""),
+            };
 
-            println!("  {DIM}#{id}{RESET}  {PURPLE}{channel}{RESET}  {GREEN}{duration_str}{RESET}");
+            let injection = injection_tag(&req["injection"]);
+
+            println!(
+                "  {DIM}#{id}{RESET}  {PURPLE}{channel}{RESET}  {color}{duration_str}{tag}{RESET}{injection}"
+            );
             println!("  {GRAY}→ {msg}{RESET}");
             println!("  {GRAY}← {resp}{RESET}");
             println!();
@@ -124,6 +216,54 @@ fn render(data: &serde_json::Value) {
     }
 }
 
+/// `  ▲ high` for requests above normal priority, `  ▼ low` below it.
+fn priority_tag(priority: &serde_json::Value) -> String {
+    match priority.as_str() {
+        Some("urgent") => format!("  {RED}▲ urgent{RESET}"),
+        Some("high") => format!("  {ORANGE}▲ high{RESET}"),
+        Some("low") => format!("  {GRAY}▼ low{RESET}"),
+        _ => String::new(),
+    }
+}
+
+/// `  ⚠ injection: high (jailbreak)` for requests that matched a pattern.
+fn injection_tag(injection: &serde_json::Value) -> String {
+    let Some(severity) = injection["severity"].as_str() else {
+        return String::new();
+    };
+    let categories: Vec<&str> = injection["categories"]
+        .as_array()
+        .map(|c| c.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    let color = if severity == "high" { RED } else { ORANGE };
+    let action = match injection["action"].as_str() {
+        Some(action) if action != "warn" => format!(" → {action}"),
+        _ => String::new(),
+    };
+    format!(
+        "  {color}⚠ injection: {severity} ({}){action}{RESET}",
+        categories.join(", ").replace('_', " ")
+    )
+}
+
+/// `3/10 available · 45/500 today` for one channel or sender.
+fn usage_summary(usage: &serde_json::Value) -> String {
+    let mut parts = Vec::new();
+    if let (Some(available), Some(burst)) = (usage["available"].as_u64(), usage["burst"].as_u64()) {
+        let color = if available == 0 { RED } else { GREEN };
+        parts.push(format!("{color}{available}/{burst} available{RESET}"));
+    }
+    let today = usage["today"].as_u64().unwrap_or(0);
+    match usage["daily"].as_u64() {
+        Some(daily) => {
+            let color = if today >= daily { RED } else { GRAY };
+            parts.push(format!("{color}{today}/{daily} today{RESET}"));
+        }
+        None => parts.push(format!("{GRAY}{today} today{RESET}")),
+    }
+    parts.join(&format!(" {DIM}·{RESET} "))
+}
+
 fn fmt_duration(secs: u64) -> String {
     let m = secs / 60;
     let s = secs % 60;
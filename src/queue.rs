@@ -1,214 +1,979 @@
-use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::{oneshot, Mutex, Notify};
-use tracing::{info, warn};
+use tokio::sync::{mpsc, oneshot, Mutex, Notify};
+use tracing::info;
 
-use crate::claude;
-use crate::config::Config;
-use crate::handlers::chat::{CallbackConfig, RequestMetadata};
-use crate::tracker::RequestTracker;
+use crate::audit::{self, AuditEntry, AuditLog};
+use crate::callbacks::{self, Outgoing};
+use crate::claude::{self, Invocation, StreamEvent};
+use crate::handlers::chat::{self, CallbackConfig, RequestMetadata};
+use crate::injection::{Action, Detection};
+use crate::metrics::METRICS;
+use crate::routing;
+use crate::session::SessionStore;
+use crate::settings::SharedSettings;
+use crate::store::{self, HistoryRecord};
+use crate::tracker::{self, Outcome, RequestTracker};
+use crate::trust::TrustLevel;
 use crate::voice_session::VoiceSessionTracker;
+use crate::webhook::Webhooks;
+
+/// What a worker sends back to whoever submitted the request.
+pub struct Reply {
+    pub text: String,
+    pub outcome: Outcome,
+}
 
 pub struct QueuedRequest {
+    /// Id reserved from the tracker at submission.
+    pub id: u64,
     pub channel: String,
     pub sender: String,
+    /// Session this request belongs to. Requests sharing a key are never
+    /// processed concurrently.
+    pub session_key: String,
+    /// Trust level of the channel at submission, or untrusted if the
+    /// injection policy downgraded the request; selects the tool policy.
+    pub trust: TrustLevel,
+    /// Injection scan of the original message.
+    pub injection: Detection,
+    /// Scheduling class, already capped for the trust level.
+    pub priority: Priority,
     pub metadata: RequestMetadata,
     pub callback: Option<CallbackConfig>,
     pub prompt: String,
     pub original_message: String,
-    pub respond: oneshot::Sender<String>,
+    pub respond: oneshot::Sender<Reply>,
+    /// Set for streaming requests; receives partial output while Claude runs.
+    pub events: Option<mpsc::UnboundedSender<StreamEvent>>,
+    /// Fires to kill the Claude subprocess once the request is running.
+    pub cancel: oneshot::Receiver<()>,
+}
+
+/// Scheduling class of a request. Workers always take from the highest
+/// class that has a runnable request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+            Self::Urgent => "urgent",
+        })
+    }
+}
+
+/// Highest priority each trust level may ask for, from the
+/// `[queue.max_priority]` config table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriorityCaps {
+    pub trusted: Priority,
+    pub verified: Priority,
+    pub untrusted: Priority,
+}
+
+impl Default for PriorityCaps {
+    fn default() -> Self {
+        Self {
+            trusted: Priority::Urgent,
+            verified: Priority::High,
+            untrusted: Priority::Normal,
+        }
+    }
+}
+
+impl PriorityCaps {
+    /// `requested` (normal if unset), lowered to what `level` allows.
+    pub fn cap(&self, level: TrustLevel, requested: Option<Priority>) -> Priority {
+        let max = match level {
+            TrustLevel::Trusted => self.trusted,
+            TrustLevel::Verified => self.verified,
+            TrustLevel::Untrusted => self.untrusted,
+        };
+        requested.unwrap_or_default().min(max)
+    }
+}
+
+/// Limits and scheduling weights, from the `[queue]` config table.
+#[derive(Clone, Debug)]
+pub struct QueueOptions {
+    /// Requests allowed to wait at once; `send` fails beyond this.
+    pub max_depth: usize,
+    /// How far ahead of its arrival time a priority request is placed.
+    pub priority_boost: Duration,
+    /// Consecutive picks each channel gets in the rotation. Default 1.
+    pub weights: BTreeMap<String, u32>,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 1000,
+            priority_boost: Duration::from_secs(60),
+            weights: BTreeMap::new(),
+        }
+    }
+}
+
+/// Returned by `send` when the queue is at `max_depth`.
+#[derive(Debug)]
+pub struct QueueFull;
+
+struct Entry {
+    req: QueuedRequest,
+    arrived: Instant,
+    /// Arrival time, moved `priority_boost` earlier for priority requests.
+    /// Orders requests within a channel.
+    key: Instant,
+    boosted: bool,
+    /// Set when an admin moves the request to the front; the most recent
+    /// move runs first.
+    pinned: Option<u64>,
+}
+
+impl Entry {
+    fn new(req: QueuedRequest, key: Instant, boosted: bool) -> Self {
+        Self {
+            req,
+            arrived: Instant::now(),
+            key,
+            boosted,
+            pinned: None,
+        }
+    }
+}
+
+/// A waiting request, as listed by `GET /api/queue`.
+#[derive(Clone, Debug, Serialize)]
+pub struct QueuedSnapshot {
+    /// Expected place in the run order, starting at 1.
+    pub position: usize,
+    pub id: u64,
+    pub channel: String,
+    pub sender: String,
+    pub priority: Priority,
+    pub message_preview: String,
+    pub waited_secs: u64,
+}
+
+struct Inner {
+    pending: Vec<Entry>,
+    /// Session keys currently being processed by a worker.
+    busy: HashSet<String>,
+    /// Requests waiting for an admin to approve or reject them, keeping
+    /// their arrival time for when they are approved.
+    held: Vec<Entry>,
+    /// Channel served last in the rotation, and how many times in a row.
+    last: Option<(String, u32)>,
+    /// Number of moves to the front so far.
+    pins: u64,
+}
+
+impl Inner {
+    /// Index of the next request to run, if any has an idle session.
+    fn next(&mut self, options: &QueueOptions) -> Option<usize> {
+        let (pending, busy) = (&self.pending, &self.busy);
+        pick(
+            pending,
+            |i| busy.contains(&pending[i].req.session_key),
+            &mut self.last,
+            options,
+        )
+    }
+
+    /// Indices of all waiting requests in the order they would run if no
+    /// session were busy and nothing else arrived.
+    fn order(&self, options: &QueueOptions) -> Vec<usize> {
+        let mut taken = vec![false; self.pending.len()];
+        let mut last = self.last.clone();
+        let mut order = Vec::with_capacity(self.pending.len());
+        while let Some(i) = pick(&self.pending, |i| taken[i], &mut last, options) {
+            taken[i] = true;
+            order.push(i);
+        }
+        order
+    }
 }
 
-/// Priority-aware FIFO queue. Supports normal `send` (back of queue)
-/// and `send_priority` (front of queue) for cross-channel conversation merging.
+/// Index of the next request to run among those `skip` does not exclude,
+/// advancing the rotation in `last`.
+///
+/// Only the earliest-arrived request of each session is eligible, so
+/// priority can reorder sessions but never requests within one. Requests
+/// moved to the front go first. Otherwise only the highest
+/// priority class with a candidate is considered. Among those, each channel
+/// offers its earliest-keyed request. Channels take turns, each getting up
+/// to its weight in picks before the next channel (in name order) is
+/// served. A priority request skips the rotation only while it is the
+/// earliest-keyed of all, so once a normal request has waited longer than
+/// the boost, bumps stop jumping ahead of it.
+fn pick(
+    pending: &[Entry],
+    skip: impl Fn(usize) -> bool,
+    last: &mut Option<(String, u32)>,
+    options: &QueueOptions,
+) -> Option<usize> {
+    let mut oldest: BTreeMap<&str, usize> = BTreeMap::new();
+    for i in (0..pending.len()).filter(|&i| !skip(i)) {
+        let first = oldest
+            .entry(pending[i].req.session_key.as_str())
+            .or_insert(i);
+        if (pending[i].arrived, i) < (pending[*first].arrived, *first) {
+            *first = i;
+        }
+    }
+    let mut eligible: Vec<usize> = oldest.into_values().collect();
+    eligible.sort_unstable();
+    let candidates = || eligible.iter().copied();
+    if let Some(i) = candidates()
+        .filter(|&i| pending[i].pinned.is_some())
+        .max_by_key(|&i| pending[i].pinned)
+    {
+        return Some(i);
+    }
+    let top = candidates().map(|i| pending[i].req.priority).max()?;
+
+    let mut heads: BTreeMap<&str, usize> = BTreeMap::new();
+    for i in candidates().filter(|&i| pending[i].req.priority == top) {
+        let head = heads.entry(pending[i].req.channel.as_str()).or_insert(i);
+        if pending[i].key < pending[*head].key {
+            *head = i;
+        }
+    }
+
+    let &earliest = heads.values().min_by_key(|&&i| pending[i].key)?;
+    if pending[earliest].boosted {
+        return Some(earliest);
+    }
+
+    let weight = |channel: &str| options.weights.get(channel).copied().unwrap_or(1);
+    let channel = match &*last {
+        Some((last, streak)) if heads.contains_key(last.as_str()) && *streak < weight(last) => {
+            last.as_str()
+        }
+        Some((last, _)) => heads
+            .keys()
+            .find(|c| **c > last.as_str())
+            .or_else(|| heads.keys().next())
+            .copied()?,
+        None => heads.keys().next().copied()?,
+    };
+    let index = heads[channel];
+
+    let streak = match &*last {
+        Some((last, streak)) if last == channel => streak + 1,
+        _ => 1,
+    };
+    *last = Some((channel.to_string(), streak));
+    Some(index)
+}
+
+/// Bounded multi-level queue with fair scheduling across channels. Higher
+/// [`Priority`] classes always run first. `send` appends to
+/// the sender's channel; `send_priority` places a request ahead for
+/// cross-channel conversation merging (see [`pick`] for how bumps age
+/// out).
+///
+/// `recv` skips requests whose session is already being processed, so
+/// requests in the same session stay ordered while unrelated sessions run
+/// in parallel.
+///
+/// Requests that need human approval are parked with `hold` and only join
+/// the queue once approved.
 #[derive(Clone)]
 pub struct Queue {
-    inner: Arc<Mutex<VecDeque<QueuedRequest>>>,
+    inner: Arc<Mutex<Inner>>,
     notify: Arc<Notify>,
+    options: Arc<QueueOptions>,
 }
 
 impl Queue {
-    fn new() -> Self {
+    pub fn new(options: QueueOptions) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(VecDeque::with_capacity(64))),
+            inner: Arc::new(Mutex::new(Inner {
+                pending: Vec::with_capacity(64),
+                busy: HashSet::new(),
+                held: Vec::new(),
+                last: None,
+                pins: 0,
+            })),
             notify: Arc::new(Notify::new()),
+            options: Arc::new(options),
         }
     }
 
-    /// Enqueue at the back (normal FIFO ordering).
-    pub async fn send(&self, req: QueuedRequest) {
-        self.inner.lock().await.push_back(req);
-        self.notify.notify_one();
+    /// Enqueue behind the channel's waiting requests.
+    pub async fn send(&self, req: QueuedRequest) -> Result<(), QueueFull> {
+        self.push(req, false).await
     }
 
-    /// Enqueue at the front (priority — next to be processed).
-    pub async fn send_priority(&self, req: QueuedRequest) {
+    /// Enqueue ahead of normal requests (cross-channel conversation merge).
+    pub async fn send_priority(&self, req: QueuedRequest) -> Result<(), QueueFull> {
         info!(
             "[{}] sender={} Priority enqueue (cross-channel merge)",
             req.channel, req.sender
         );
-        self.inner.lock().await.push_front(req);
-        self.notify.notify_one();
+        METRICS.priority_enqueues.inc();
+        self.push(req, true).await
     }
 
-    /// Wait for and take the next request.
+    async fn push(&self, req: QueuedRequest, boosted: bool) -> Result<(), QueueFull> {
+        let mut inner = self.inner.lock().await;
+        if inner.pending.len() >= self.options.max_depth {
+            return Err(QueueFull);
+        }
+        let now = Instant::now();
+        let key = if boosted {
+            now.checked_sub(self.options.priority_boost).unwrap_or(now)
+        } else {
+            now
+        };
+        inner.pending.push(Entry::new(req, key, boosted));
+        drop(inner);
+        self.notify.notify_waiters();
+        Ok(())
+    }
+
+    /// Number of requests waiting for a worker.
+    pub async fn len(&self) -> usize {
+        self.inner.lock().await.pending.len()
+    }
+
+    /// Remove a request that has not been picked up by a worker yet,
+    /// whether queued or held.
+    pub async fn remove(&self, id: u64) -> Option<QueuedRequest> {
+        let mut inner = self.inner.lock().await;
+        match inner.pending.iter().position(|e| e.req.id == id) {
+            Some(pos) => Some(inner.pending.remove(pos).req),
+            None => take(&mut inner.held, id).map(|e| e.req),
+        }
+    }
+
+    /// Waiting requests in their expected run order.
+    pub async fn snapshot(&self) -> Vec<QueuedSnapshot> {
+        let inner = self.inner.lock().await;
+        inner
+            .order(&self.options)
+            .into_iter()
+            .enumerate()
+            .map(|(n, i)| {
+                let entry = &inner.pending[i];
+                QueuedSnapshot {
+                    position: n + 1,
+                    id: entry.req.id,
+                    channel: entry.req.channel.clone(),
+                    sender: entry.req.sender.clone(),
+                    priority: entry.req.priority,
+                    message_preview: tracker::preview(&entry.req.original_message),
+                    waited_secs: entry.arrived.elapsed().as_secs(),
+                }
+            })
+            .collect()
+    }
+
+    /// Run a waiting request before any other. Returns false if it is not
+    /// queued.
+    pub async fn move_to_front(&self, id: u64) -> bool {
+        let mut inner = self.inner.lock().await;
+        let pin = inner.pins + 1;
+        let Some(entry) = inner.pending.iter_mut().find(|e| e.req.id == id) else {
+            return false;
+        };
+        entry.pinned = Some(pin);
+        inner.pins = pin;
+        drop(inner);
+        self.notify.notify_waiters();
+        true
+    }
+
+    /// Remove every waiting request on `channel`, oldest first.
+    pub async fn drain_channel(&self, channel: &str) -> Vec<QueuedRequest> {
+        let mut inner = self.inner.lock().await;
+        let (drained, kept) = std::mem::take(&mut inner.pending)
+            .into_iter()
+            .partition(|e| e.req.channel == channel);
+        inner.pending = kept;
+        drained.into_iter().map(|e| e.req).collect()
+    }
+
+    /// Park a request until it is approved or rejected.
+    pub async fn hold(&self, req: QueuedRequest) {
+        let now = Instant::now();
+        self.inner
+            .lock()
+            .await
+            .held
+            .push(Entry::new(req, now, false));
+    }
+
+    /// Move a held request into the queue. It was accepted when it was
+    /// held, so `max_depth` does not apply, and it keeps its arrival time,
+    /// taking its original place in its session. Returns false if no
+    /// request with that id is held.
+    pub async fn approve(&self, id: u64) -> bool {
+        let mut inner = self.inner.lock().await;
+        let Some(entry) = take(&mut inner.held, id) else {
+            return false;
+        };
+        inner.pending.push(entry);
+        drop(inner);
+        self.notify.notify_waiters();
+        true
+    }
+
+    /// Take a held request out, e.g. to reject it.
+    pub async fn take_held(&self, id: u64) -> Option<QueuedRequest> {
+        take(&mut self.inner.lock().await.held, id).map(|e| e.req)
+    }
+
+    /// Wait for and take the next request whose session is idle, marking
+    /// that session busy until [`Queue::release`] is called.
     async fn recv(&self) -> QueuedRequest {
         loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
             {
-                let mut queue = self.inner.lock().await;
-                if let Some(req) = queue.pop_front() {
+                let mut inner = self.inner.lock().await;
+                if let Some(pos) = inner.next(&self.options) {
+                    let req = inner.pending.remove(pos).req;
+                    inner.busy.insert(req.session_key.clone());
                     return req;
                 }
             }
-            self.notify.notified().await;
+
+            notified.await;
         }
     }
+
+    /// Mark a session idle again so its next request can be picked up.
+    async fn release(&self, session_key: &str) {
+        self.inner.lock().await.busy.remove(session_key);
+        self.notify.notify_waiters();
+    }
 }
 
-pub fn spawn(
-    config: Config,
-    tracker: RequestTracker,
-    voice_sessions: VoiceSessionTracker,
-) -> Queue {
-    let queue = Queue::new();
-    let worker_queue = queue.clone();
-    tokio::spawn(worker(worker_queue, config, tracker, voice_sessions));
-    queue
+fn take(held: &mut Vec<Entry>, id: u64) -> Option<Entry> {
+    let pos = held.iter().position(|e| e.req.id == id)?;
+    Some(held.remove(pos))
 }
 
-async fn worker(
-    queue: Queue,
-    config: Config,
-    tracker: RequestTracker,
-    voice_sessions: VoiceSessionTracker,
+/// Answer a request without running it, recording it as finished with
+/// `outcome`. Used for requests the injection policy blocks and for held
+/// requests that are rejected.
+pub async fn settle(
+    req: QueuedRequest,
+    text: &str,
+    outcome: Outcome,
+    tracker: &RequestTracker,
+    audit: Option<&AuditLog>,
 ) {
-    let mut session_id: Option<String> = None;
-    let mut last_used = Instant::now();
-    let timeout = Duration::from_secs(config.session_ttl_secs);
+    let injection = req.injection.is_detected().then(|| req.injection.clone());
+    let now_unix = store::now_unix();
+    tracker
+        .record(HistoryRecord {
+            id: req.id,
+            channel: req.channel.clone(),
+            sender: req.sender.clone(),
+            message: req.original_message,
+            response: text.to_string(),
+            started_unix: now_unix,
+            completed_unix: now_unix,
+            duration_secs: 0,
+            outcome,
+            injection,
+        })
+        .await;
+    if let Some(audit) = audit {
+        audit.record(AuditEntry {
+            request_id: req.id,
+            channel: req.channel,
+            sender: req.sender,
+            trust: req.trust,
+            injection: req.injection,
+            prompt_sha256: audit::sha256_hex(&req.prompt),
+            session_id: None,
+            outcome,
+            exit_code: None,
+            duration_ms: 0,
+            response: text.to_string(),
+        });
+    }
+    let _ = req.respond.send(Reply {
+        text: text.to_string(),
+        outcome,
+    });
+}
+
+/// Shared services each worker uses while running a request.
+#[derive(Clone)]
+pub struct WorkerContext {
+    pub settings: SharedSettings,
+    pub tracker: RequestTracker,
+    pub voice_sessions: VoiceSessionTracker,
+    pub sessions: SessionStore,
+    pub audit: Option<AuditLog>,
+    pub webhooks: Webhooks,
+}
+
+pub fn spawn(workers: usize, context: WorkerContext) -> Queue {
+    let queue = Queue::new(context.settings.load().config.queue_options());
+    let workers = workers.max(1);
+    info!("Starting {workers} Claude worker(s)");
+    for slot in 0..workers {
+        tokio::spawn(worker(slot, queue.clone(), context.clone()));
+    }
+    queue
+}
+
+async fn worker(slot: usize, queue: Queue, context: WorkerContext) {
+    let WorkerContext {
+        settings,
+        tracker,
+        voice_sessions,
+        sessions,
+        audit,
+        webhooks,
+    } = context;
     let http_client = reqwest::Client::new();
 
     loop {
         let req = queue.recv().await;
+        let settings = settings.load();
+        let config = &settings.config;
 
-        // Check idle timeout
-        if last_used.elapsed() > timeout && session_id.is_some() {
-            info!("Session expired after idle timeout, starting fresh");
-            session_id = None;
-        }
+        let session_id = sessions.get(&req.session_key).await;
 
         // Track voice sessions: if this is a voice request, register/refresh
         if req.channel == "voice" {
             if let Some(call_sid) = &req.metadata.call_sid {
-                voice_sessions.touch(&req.sender, call_sid).await;
+                voice_sessions.touch(&req.sender, call_sid, None).await;
             }
         }
 
-        let request_id = tracker
-            .start(&req.channel, &req.sender, &req.original_message)
-            .await;
+        tracker.start(&req, slot).await;
 
         let self_doc = config
             .self_path
             .as_deref()
             .and_then(|path| std::fs::read_to_string(path).ok());
 
-        let response = claude::invoke(
-            &config.claude_bin,
-            &req.prompt,
-            &config.home,
-            session_id.as_deref(),
-            self_doc.as_deref(),
-        )
-        .await;
+        let policy = match req.injection.action {
+            Some(Action::Downgrade) => config.permissions.downgraded(),
+            _ => config.permissions.for_level(req.trust).clone(),
+        };
 
-        tracker.complete(request_id, &response.text).await;
+        let started = Instant::now();
+        let response = claude::invoke(Invocation {
+            claude_bin: &config.claude_bin,
+            prompt: &req.prompt,
+            home: &config.home,
+            session_id: session_id.as_deref(),
+            self_doc: self_doc.as_deref(),
+            policy: &policy,
+            timeout: Duration::from_secs(config.timeout_secs),
+            events: req.events.as_ref(),
+            cancel: Some(req.cancel),
+        })
+        .await;
+        let elapsed = started.elapsed();
+        METRICS
+            .request_duration
+            .observe(&req.channel, elapsed.as_secs_f64());
 
-        if let Some(sid) = &response.session_id {
-            session_id = Some(sid.clone());
+        if let Some(audit) = &audit {
+            audit.record(AuditEntry {
+                request_id: req.id,
+                channel: req.channel.clone(),
+                sender: req.sender.clone(),
+                trust: req.trust,
+                injection: req.injection.clone(),
+                prompt_sha256: audit::sha256_hex(&req.prompt),
+                session_id: response.session_id.clone().or(session_id),
+                outcome: response.outcome,
+                exit_code: response.exit_code,
+                duration_ms: elapsed.as_millis() as u64,
+                response: response.text.clone(),
+            });
         }
 
-        last_used = Instant::now();
+        tracker
+            .complete(req.id, &response.text, response.outcome)
+            .await;
 
-        let truncated = if response.text.len() > 120 {
-            format!("{}...", &response.text[..120])
-        } else {
-            response.text.clone()
-        };
+        sessions
+            .update(&req.session_key, response.session_id.as_deref())
+            .await;
+        queue.release(&req.session_key).await;
+
+        if response.outcome == Outcome::Cancelled {
+            info!("[{}] sender={} Request cancelled", req.channel, req.sender);
+            let _ = req.respond.send(Reply {
+                text: response.text,
+                outcome: response.outcome,
+            });
+            continue;
+        }
+
+        let truncated = chat::truncate_str(&response.text, 120);
         info!(
             "[{}] sender={} Response: {truncated}",
             req.channel, req.sender
         );
 
-        // Cross-channel voice routing: if this request came from a non-voice
-        // channel and the sender has an active voice call, inject the response
-        // into the call instead of returning it on the original channel.
-        let mut injected = false;
-        if req.channel != "voice" {
-            if let Some(call_sid) = voice_sessions.active_call_sid(&req.sender).await {
-                if let Some(ref voice_url) = config.voice_echo_url {
-                    info!(
-                        "[{}] sender={} Routing response to active voice call {}",
-                        req.channel, req.sender, call_sid
-                    );
-                    let inject_url = format!("{}/api/inject", voice_url.trim_end_matches('/'));
-                    let mut inject_req = http_client.post(&inject_url).json(&serde_json::json!({
-                        "call_sid": call_sid,
-                        "text": &response.text,
-                    }));
-                    if let Some(ref token) = config.voice_echo_token {
-                        inject_req = inject_req.bearer_auth(token);
-                    }
-                    match inject_req.send().await {
-                        Ok(resp) if resp.status().is_success() => {
-                            info!("[{}] Response injected into voice call", req.channel);
-                            injected = true;
-                        }
-                        Ok(resp) => {
-                            warn!(
-                                "[{}] Voice inject failed (HTTP {}), falling back to original channel",
-                                req.channel,
-                                resp.status()
-                            );
-                        }
-                        Err(e) => {
-                            warn!(
-                                "[{}] Voice inject request failed: {e}, falling back to original channel",
-                                req.channel
-                            );
-                        }
-                    }
-                }
-            }
+        // Fan the response out along the routing rules. A route may answer
+        // the caller with a short reply instead, e.g. when the response is
+        // spoken into the sender's active call.
+        let out = Outgoing {
+            request_id: req.id,
+            channel: req.channel.clone(),
+            sender: req.sender.clone(),
+            metadata: req.metadata.clone(),
+            response: response.text.clone(),
+        };
+        let active_call = voice_sessions.active_call_sid(&req.sender).await;
+        let reply = routing::apply(
+            &settings,
+            &out,
+            req.trust,
+            active_call.as_deref(),
+            &webhooks,
+            &http_client,
+        )
+        .await;
+
+        // Route response via callback if configured. Delivery runs in the
+        // background so a slow target does not hold up the worker.
+        if let Some(cb) = req.callback.clone() {
+            let (settings, webhooks, client) =
+                (settings.clone(), webhooks.clone(), http_client.clone());
+            tokio::spawn(async move {
+                callbacks::dispatch(&cb, out, &settings.config, &webhooks, &client).await;
+            });
         }
 
-        // Route response via callback if configured
-        if let Some(cb) = &req.callback {
-            if cb.callback_type == "webhook" {
-                if let Some(url) = &cb.url {
-                    let payload = serde_json::json!({
-                        "response": &response.text,
-                        "channel": &req.channel,
-                        "sender": &req.sender,
-                        "metadata": {
-                            "call_sid": &req.metadata.call_sid,
-                            "discord_channel_id": &req.metadata.discord_channel_id,
-                            "workflow_id": &req.metadata.workflow_id,
-                        }
-                    });
-                    if let Err(e) = http_client.post(url).json(&payload).send().await {
-                        warn!("Callback webhook failed: {e}");
-                    }
-                }
-            }
+        let _ = req.respond.send(Reply {
+            text: reply.unwrap_or(response.text),
+            outcome: response.outcome,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionScope;
+
+    fn request(session_key: &str, message: &str) -> QueuedRequest {
+        let (tx, _rx) = oneshot::channel();
+        let (_cancel_tx, cancel) = oneshot::channel();
+        QueuedRequest {
+            id: 0,
+            channel: "discord".into(),
+            sender: "D".into(),
+            session_key: session_key.into(),
+            trust: TrustLevel::Verified,
+            injection: Detection::default(),
+            priority: Priority::Normal,
+            metadata: RequestMetadata::default(),
+            callback: None,
+            prompt: message.into(),
+            original_message: message.into(),
+            respond: tx,
+            events: None,
+            cancel,
         }
+    }
 
-        // Send response back via oneshot. If injected into voice, send
-        // a brief ack instead of the full response.
-        if injected {
-            let _ = req.respond.send("Responding on call.".to_string());
-        } else {
-            let _ = req.respond.send(response.text);
+    fn on(channel: &str, session_key: &str, message: &str) -> QueuedRequest {
+        QueuedRequest {
+            channel: channel.into(),
+            ..request(session_key, message)
+        }
+    }
+
+    async fn drain(queue: &Queue, n: usize) -> Vec<String> {
+        let mut order = Vec::new();
+        for _ in 0..n {
+            order.push(queue.recv().await.original_message);
         }
+        order
+    }
+
+    #[tokio::test]
+    async fn full_queue_rejects_new_requests() {
+        let queue = Queue::new(QueueOptions {
+            max_depth: 2,
+            ..QueueOptions::default()
+        });
+        queue.send(request("a", "first")).await.unwrap();
+        queue.send_priority(request("b", "second")).await.unwrap();
+        assert!(queue.send(request("c", "third")).await.is_err());
+        assert!(queue.send_priority(request("c", "third")).await.is_err());
+
+        queue.recv().await;
+        assert!(queue.send(request("c", "third")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn channels_take_turns_by_weight() {
+        let queue = Queue::new(QueueOptions {
+            weights: [("voice".to_string(), 2)].into(),
+            ..QueueOptions::default()
+        });
+        for i in 1..=3 {
+            let (d, v) = (format!("d{i}"), format!("v{i}"));
+            queue.send(on("discord", &d, &d)).await.unwrap();
+            queue.send(on("voice", &v, &v)).await.unwrap();
+        }
+        assert_eq!(drain(&queue, 6).await, ["d1", "v1", "v2", "d2", "v3", "d3"]);
+    }
+
+    #[tokio::test]
+    async fn priority_bumps_age_out() {
+        let queue = Queue::new(QueueOptions::default());
+        queue.send(on("discord", "a", "normal")).await.unwrap();
+        queue
+            .send_priority(on("voice", "b", "bumped"))
+            .await
+            .unwrap();
+        assert_eq!(drain(&queue, 2).await, ["bumped", "normal"]);
+
+        // A request that has waited longer than the boost is not overtaken.
+        let queue = Queue::new(QueueOptions {
+            priority_boost: Duration::from_millis(50),
+            ..QueueOptions::default()
+        });
+        queue.send(on("discord", "a", "normal")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        queue
+            .send_priority(on("voice", "b", "bumped"))
+            .await
+            .unwrap();
+        assert_eq!(drain(&queue, 2).await, ["normal", "bumped"]);
+    }
+
+    #[tokio::test]
+    async fn held_requests_wait_for_approval() {
+        let queue = Queue::new(QueueOptions::default());
+        let mut held = request("a", "held");
+        held.id = 1;
+        queue.hold(held).await;
+        queue.send(request("b", "normal")).await.unwrap();
+
+        assert_eq!(queue.recv().await.original_message, "normal");
+        let idle = tokio::time::timeout(Duration::from_millis(50), queue.recv()).await;
+        assert!(idle.is_err());
+
+        assert!(queue.approve(1).await);
+        assert!(!queue.approve(1).await);
+        assert_eq!(queue.recv().await.original_message, "held");
+    }
+
+    #[tokio::test]
+    async fn approved_requests_keep_their_place_in_the_session() {
+        let queue = Queue::new(QueueOptions::default());
+        let mut held = request("a", "held");
+        held.id = 1;
+        queue.hold(held).await;
+        queue.send(request("a", "later")).await.unwrap();
+
+        assert!(queue.approve(1).await);
+        let first = queue.recv().await;
+        assert_eq!(first.original_message, "held");
+        queue.release(&first.session_key).await;
+        assert_eq!(queue.recv().await.original_message, "later");
+    }
+
+    #[tokio::test]
+    async fn priority_never_reorders_a_session() {
+        let queue = Queue::new(QueueOptions::default());
+        queue.send(request("a", "first")).await.unwrap();
+        let mut urgent = request("a", "second");
+        urgent.priority = Priority::Urgent;
+        queue.send(urgent).await.unwrap();
+        queue.send(request("b", "other")).await.unwrap();
+
+        let first = queue.recv().await;
+        assert_eq!(first.original_message, "first");
+        queue.release(&first.session_key).await;
+        assert_eq!(drain(&queue, 2).await, ["second", "other"]);
+    }
+
+    #[tokio::test]
+    async fn sessions_spanning_channels_stay_ordered() {
+        let sessions = SessionStore::new(SessionScope::Sender, 60, None);
+        let queue = Queue::new(QueueOptions {
+            weights: [("voice".to_string(), 3)].into(),
+            ..QueueOptions::default()
+        });
+        let key = |channel| sessions.key(channel, "D");
+        queue
+            .send(on("discord", &key("discord"), "older"))
+            .await
+            .unwrap();
+        // A bumped request from the same sender on another channel would
+        // otherwise skip the rotation and run first.
+        queue
+            .send_priority(on("voice", &key("voice"), "newer"))
+            .await
+            .unwrap();
+
+        let first = queue.recv().await;
+        assert_eq!(first.original_message, "older");
+        queue.release(&first.session_key).await;
+        assert_eq!(queue.recv().await.original_message, "newer");
+    }
+
+    #[tokio::test]
+    async fn higher_classes_run_first() {
+        let queue = Queue::new(QueueOptions::default());
+        let at = |priority, session, msg| QueuedRequest {
+            priority,
+            ..request(session, msg)
+        };
+        queue.send(at(Priority::Low, "r", "low")).await.unwrap();
+        queue
+            .send(at(Priority::Normal, "d", "normal"))
+            .await
+            .unwrap();
+        queue
+            .send(at(Priority::Urgent, "v", "urgent"))
+            .await
+            .unwrap();
+        queue.send(at(Priority::High, "v", "high")).await.unwrap();
+
+        // "high" shares a session with "urgent", so it waits for the release
+        // while lower classes run.
+        assert_eq!(drain(&queue, 3).await, ["urgent", "normal", "low"]);
+        queue.release("v").await;
+        assert_eq!(queue.recv().await.original_message, "high");
+    }
+
+    #[test]
+    fn priority_is_capped_by_trust_level() {
+        let caps = PriorityCaps::default();
+        let urgent = Some(Priority::Urgent);
+        assert_eq!(caps.cap(TrustLevel::Trusted, urgent), Priority::Urgent);
+        assert_eq!(caps.cap(TrustLevel::Verified, urgent), Priority::High);
+        assert_eq!(caps.cap(TrustLevel::Untrusted, urgent), Priority::Normal);
+        assert_eq!(
+            caps.cap(TrustLevel::Untrusted, Some(Priority::Low)),
+            Priority::Low
+        );
+        assert_eq!(caps.cap(TrustLevel::Trusted, None), Priority::Normal);
+    }
+
+    #[tokio::test]
+    async fn snapshot_lists_run_order() {
+        let queue = Queue::new(QueueOptions::default());
+        for (id, channel, priority) in [
+            (1, "discord", Priority::Normal),
+            (2, "discord", Priority::Normal),
+            (3, "voice", Priority::Normal),
+            (4, "reflection", Priority::Low),
+            (5, "voice", Priority::High),
+        ] {
+            let req = QueuedRequest {
+                id,
+                priority,
+                ..on(channel, &id.to_string(), "msg")
+            };
+            queue.send(req).await.unwrap();
+        }
+
+        let ids = |list: Vec<QueuedSnapshot>| list.iter().map(|q| q.id).collect::<Vec<_>>();
+        let listed = queue.snapshot().await;
+        assert_eq!(ids(listed.clone()), [5, 1, 3, 2, 4]);
+        assert_eq!(listed[0].position, 1);
+        assert_eq!(listed[0].channel, "voice");
+
+        assert!(queue.move_to_front(4).await);
+        assert!(queue.move_to_front(2).await);
+        assert!(!queue.move_to_front(9).await);
+        assert_eq!(ids(queue.snapshot().await), [2, 4, 5, 1, 3]);
+        assert_eq!(queue.recv().await.id, 2);
+    }
+
+    #[tokio::test]
+    async fn drain_removes_one_channel() {
+        let queue = Queue::new(QueueOptions::default());
+        queue.send(on("discord", "a", "d1")).await.unwrap();
+        queue.send(on("voice", "b", "v1")).await.unwrap();
+        queue.send(on("discord", "c", "d2")).await.unwrap();
+
+        let drained: Vec<_> = queue
+            .drain_channel("discord")
+            .await
+            .into_iter()
+            .map(|r| r.original_message)
+            .collect();
+        assert_eq!(drained, ["d1", "d2"]);
+        assert_eq!(queue.len().await, 1);
+        assert_eq!(queue.recv().await.original_message, "v1");
+    }
+
+    #[tokio::test]
+    async fn same_session_waits_for_release() {
+        let queue = Queue::new(QueueOptions::default());
+        queue.send(request("a", "first")).await.unwrap();
+        queue.send(request("a", "second")).await.unwrap();
+
+        let first = queue.recv().await;
+        assert_eq!(first.original_message, "first");
+
+        let blocked = tokio::time::timeout(Duration::from_millis(50), queue.recv()).await;
+        assert!(blocked.is_err());
+
+        queue.release("a").await;
+        let second = queue.recv().await;
+        assert_eq!(second.original_message, "second");
+    }
+
+    #[tokio::test]
+    async fn other_sessions_run_concurrently() {
+        let queue = Queue::new(QueueOptions::default());
+        queue.send(request("a", "first")).await.unwrap();
+        queue.send(request("a", "second")).await.unwrap();
+        queue.send(request("b", "third")).await.unwrap();
+
+        assert_eq!(queue.recv().await.original_message, "first");
+        assert_eq!(queue.recv().await.original_message, "third");
+    }
+
+    #[tokio::test]
+    async fn waiting_worker_wakes_on_release() {
+        let queue = Queue::new(QueueOptions::default());
+        queue.send(request("a", "first")).await.unwrap();
+        queue.send(request("a", "second")).await.unwrap();
+        let _first = queue.recv().await;
+
+        let waiter = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.recv().await.original_message }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        queue.release("a").await;
+
+        let got = tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("worker was not woken")
+            .unwrap();
+        assert_eq!(got, "second");
     }
 }
@@ -0,0 +1,475 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::store;
+
+/// Idle counters are dropped once the map grows past this many keys.
+const PRUNE_ABOVE: usize = 1024;
+const DAY_SECS: u64 = 86_400;
+
+/// Limits for one channel or sender. Unset fields are unlimited.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    /// Sustained requests per minute (token bucket refill rate).
+    pub per_minute: Option<u32>,
+    /// Requests that can be made at once before the rate applies.
+    /// Defaults to `per_minute`.
+    pub burst: Option<u32>,
+    /// Requests per UTC day.
+    pub daily: Option<u32>,
+}
+
+impl Limit {
+    /// `self` with unset fields taken from `default`.
+    fn or(&self, default: &Limit) -> Limit {
+        Limit {
+            per_minute: self.per_minute.or(default.per_minute),
+            burst: self.burst.or(default.burst),
+            daily: self.daily.or(default.daily),
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.per_minute.is_none() && self.daily.is_none()
+    }
+
+    fn capacity(&self) -> Option<f64> {
+        let per_minute = self.per_minute?;
+        Some(f64::from(self.burst.unwrap_or(per_minute)))
+    }
+}
+
+/// The `[limits]` table. `channel` and `sender` apply to every channel and
+/// sender; entries under `channels` and `senders` override them per name.
+/// Trusted channels are never limited.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub channel: Limit,
+    pub sender: Limit,
+    pub channels: BTreeMap<String, Limit>,
+    pub senders: BTreeMap<String, Limit>,
+}
+
+impl Limits {
+    pub fn validate(&self) -> Result<(), String> {
+        let all = [
+            ("limits.channel".to_string(), &self.channel),
+            ("limits.sender".to_string(), &self.sender),
+        ]
+        .into_iter()
+        .chain(
+            self.channels
+                .iter()
+                .map(|(name, l)| (format!("limits.channels.{name}"), l)),
+        )
+        .chain(
+            self.senders
+                .iter()
+                .map(|(name, l)| (format!("limits.senders.{name}"), l)),
+        );
+        for (key, limit) in all {
+            if limit.per_minute == Some(0) {
+                return Err(format!("invalid {key}.per_minute: must be at least 1"));
+            }
+            if limit.burst == Some(0) {
+                return Err(format!("invalid {key}.burst: must be at least 1"));
+            }
+        }
+        Ok(())
+    }
+
+    fn for_channel(&self, channel: &str) -> Limit {
+        self.channels
+            .get(channel)
+            .map_or_else(|| self.channel.clone(), |l| l.or(&self.channel))
+    }
+
+    fn for_sender(&self, sender: &str) -> Limit {
+        self.senders
+            .get(sender)
+            .map_or_else(|| self.sender.clone(), |l| l.or(&self.sender))
+    }
+}
+
+/// Why a request was refused.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Denied {
+    /// `channel` or `sender`.
+    pub scope: &'static str,
+    pub key: String,
+    /// The daily quota, rather than the rate, ran out.
+    pub quota: bool,
+    /// Seconds until a request would be accepted.
+    pub retry_after: u64,
+}
+
+impl Denied {
+    pub fn message(&self) -> String {
+        let what = if self.quota {
+            "daily quota exceeded"
+        } else {
+            "rate limit exceeded"
+        };
+        format!(
+            "{what} for {} {}; retry in {}s",
+            self.scope, self.key, self.retry_after
+        )
+    }
+}
+
+/// Current usage of one channel or sender, for `/api/status`.
+#[derive(Clone, Debug, Serialize)]
+pub struct UsageSnapshot {
+    /// Requests that could be made right now, if rate limited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    pub today: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily: Option<u32>,
+}
+
+struct Usage {
+    tokens: f64,
+    refilled: Instant,
+    /// UTC day `today` counts requests for.
+    day: u64,
+    today: u32,
+}
+
+impl Usage {
+    fn new(limit: &Limit, now: Instant, day: u64) -> Self {
+        Self {
+            tokens: limit.capacity().unwrap_or(0.0),
+            refilled: now,
+            day,
+            today: 0,
+        }
+    }
+
+    /// Bring the bucket and the daily count up to `now`.
+    fn refresh(&mut self, limit: &Limit, now: Instant, day: u64) {
+        if let (Some(per_minute), Some(capacity)) = (limit.per_minute, limit.capacity()) {
+            let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * f64::from(per_minute) / 60.0).min(capacity);
+        }
+        self.refilled = now;
+        if day != self.day {
+            self.day = day;
+            self.today = 0;
+        }
+    }
+
+    /// Seconds until a request fits, or `None` if one fits now. The second
+    /// value says whether the daily quota is what blocks it.
+    fn wait(&self, limit: &Limit, unix: u64) -> Option<(u64, bool)> {
+        if limit.daily.is_some_and(|daily| self.today >= daily) {
+            return Some((DAY_SECS - unix % DAY_SECS, true));
+        }
+        let per_minute = limit.per_minute?;
+        if self.tokens >= 1.0 {
+            return None;
+        }
+        let secs = (1.0 - self.tokens) * 60.0 / f64::from(per_minute);
+        Some((secs.ceil().max(1.0) as u64, false))
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+        self.today += 1;
+    }
+
+    fn give_back(&mut self, limit: &Limit) {
+        self.tokens = (self.tokens + 1.0).min(limit.capacity().unwrap_or(0.0));
+        self.today = self.today.saturating_sub(1);
+    }
+
+    fn snapshot(&self, limit: &Limit) -> UsageSnapshot {
+        UsageSnapshot {
+            available: limit
+                .per_minute
+                .map(|_| self.tokens.floor().max(0.0) as u32),
+            burst: limit.capacity().map(|c| c as u32),
+            today: self.today,
+            daily: limit.daily,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    channels: HashMap<String, Usage>,
+    senders: HashMap<String, Usage>,
+}
+
+/// Token buckets and daily counters per channel and per sender. Limits are
+/// passed in on every call, so they follow config reloads; the counters
+/// live in memory and reset on restart.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a request from `sender` on `channel`, or say why it must wait.
+    /// Nothing is counted unless both the channel and the sender allow it.
+    pub fn check(&self, limits: &Limits, channel: &str, sender: &str) -> Result<(), Denied> {
+        self.check_at(limits, channel, sender, Instant::now(), store::now_unix())
+    }
+
+    fn check_at(
+        &self,
+        limits: &Limits,
+        channel: &str,
+        sender: &str,
+        now: Instant,
+        unix: u64,
+    ) -> Result<(), Denied> {
+        let day = unix / DAY_SECS;
+        let channel_limit = limits.for_channel(channel);
+        let sender_limit = limits.for_sender(sender);
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let Inner { channels, senders } = &mut *inner;
+
+        let mut counted = Vec::new();
+        for (scope, map, key, limit) in [
+            ("channel", channels, channel, &channel_limit),
+            ("sender", senders, sender, &sender_limit),
+        ] {
+            if limit.is_unlimited() {
+                continue;
+            }
+            if map.len() > PRUNE_ABOVE {
+                prune(map, now);
+            }
+            let usage = map
+                .entry(key.to_string())
+                .or_insert_with(|| Usage::new(limit, now, day));
+            usage.refresh(limit, now, day);
+            if let Some((retry_after, quota)) = usage.wait(limit, unix) {
+                return Err(Denied {
+                    scope,
+                    key: key.to_string(),
+                    quota,
+                    retry_after,
+                });
+            }
+            counted.push(usage);
+        }
+        for usage in counted {
+            usage.take();
+        }
+        Ok(())
+    }
+
+    /// Undo a counted request that the server then refused, so it does not
+    /// use up the sender's burst or quota.
+    pub fn refund(&self, limits: &Limits, channel: &str, sender: &str) {
+        self.refund_at(limits, channel, sender, Instant::now(), store::now_unix());
+    }
+
+    fn refund_at(&self, limits: &Limits, channel: &str, sender: &str, now: Instant, unix: u64) {
+        let day = unix / DAY_SECS;
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let Inner { channels, senders } = &mut *inner;
+        for (map, key, limit) in [
+            (channels, channel, limits.for_channel(channel)),
+            (senders, sender, limits.for_sender(sender)),
+        ] {
+            if let Some(usage) = map.get_mut(key) {
+                usage.refresh(&limit, now, day);
+                usage.give_back(&limit);
+            }
+        }
+    }
+
+    /// Usage of every limited channel and sender seen since startup.
+    pub fn snapshot(&self, limits: &Limits) -> serde_json::Value {
+        self.snapshot_at(limits, Instant::now(), store::now_unix())
+    }
+
+    fn snapshot_at(&self, limits: &Limits, now: Instant, unix: u64) -> serde_json::Value {
+        let day = unix / DAY_SECS;
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let Inner { channels, senders } = &mut *inner;
+        serde_json::json!({
+            "channels": usage(channels, |c| limits.for_channel(c), now, day),
+            "senders": usage(senders, |s| limits.for_sender(s), now, day),
+        })
+    }
+}
+
+fn usage(
+    map: &mut HashMap<String, Usage>,
+    limit_for: impl Fn(&str) -> Limit,
+    now: Instant,
+    day: u64,
+) -> BTreeMap<String, UsageSnapshot> {
+    map.iter_mut()
+        .map(|(key, usage)| {
+            let limit = limit_for(key);
+            usage.refresh(&limit, now, day);
+            (key.clone(), usage.snapshot(&limit))
+        })
+        .collect()
+}
+
+/// Drop counters idle for a day; they would start fresh anyway.
+fn prune(map: &mut HashMap<String, Usage>, now: Instant) {
+    map.retain(|_, usage| {
+        now.saturating_duration_since(usage.refilled) < Duration::from_secs(DAY_SECS)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(channel: Limit, sender: Limit) -> Limits {
+        Limits {
+            channel,
+            sender,
+            ..Limits::default()
+        }
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let limiter = RateLimiter::new();
+        let limits = limits(
+            Limit::default(),
+            Limit {
+                per_minute: Some(6),
+                burst: Some(2),
+                daily: None,
+            },
+        );
+        let start = Instant::now();
+        let unix = 1_000_000;
+        assert!(limiter.check_at(&limits, "web", "a", start, unix).is_ok());
+        assert!(limiter.check_at(&limits, "web", "a", start, unix).is_ok());
+
+        let denied = limiter
+            .check_at(&limits, "web", "a", start, unix)
+            .unwrap_err();
+        assert_eq!(denied.scope, "sender");
+        assert!(!denied.quota);
+        assert_eq!(denied.retry_after, 10);
+
+        // Another sender has its own bucket.
+        assert!(limiter.check_at(&limits, "web", "b", start, unix).is_ok());
+
+        let later = start + Duration::from_secs(10);
+        assert!(limiter
+            .check_at(&limits, "web", "a", later, unix + 10)
+            .is_ok());
+    }
+
+    #[test]
+    fn daily_quota_resets_at_midnight() {
+        let limiter = RateLimiter::new();
+        let limits = limits(
+            Limit {
+                daily: Some(2),
+                ..Limit::default()
+            },
+            Limit::default(),
+        );
+        let now = Instant::now();
+        let unix = 3 * DAY_SECS + DAY_SECS - 100;
+        assert!(limiter.check_at(&limits, "web", "a", now, unix).is_ok());
+        assert!(limiter.check_at(&limits, "web", "b", now, unix).is_ok());
+
+        let denied = limiter
+            .check_at(&limits, "web", "c", now, unix)
+            .unwrap_err();
+        assert_eq!(denied.scope, "channel");
+        assert!(denied.quota);
+        assert_eq!(denied.retry_after, 100);
+
+        assert!(limiter
+            .check_at(&limits, "web", "c", now, unix + 100)
+            .is_ok());
+    }
+
+    #[test]
+    fn refused_requests_are_not_counted() {
+        let limiter = RateLimiter::new();
+        let limits = limits(
+            Limit {
+                daily: Some(1),
+                ..Limit::default()
+            },
+            Limit {
+                daily: Some(5),
+                ..Limit::default()
+            },
+        );
+        let now = Instant::now();
+        assert!(limiter.check_at(&limits, "web", "a", now, 0).is_ok());
+        assert!(limiter.check_at(&limits, "web", "a", now, 0).is_err());
+        assert!(limiter.check_at(&limits, "other", "a", now, 0).is_ok());
+
+        let usage = limiter.snapshot_at(&limits, now, 0);
+        assert_eq!(usage["senders"]["a"]["today"], 2);
+    }
+
+    #[test]
+    fn refunds_restore_burst_and_quota() {
+        let limiter = RateLimiter::new();
+        let limits = limits(
+            Limit {
+                daily: Some(1),
+                ..Limit::default()
+            },
+            Limit {
+                per_minute: Some(1),
+                burst: Some(1),
+                daily: None,
+            },
+        );
+        let now = Instant::now();
+        assert!(limiter.check_at(&limits, "web", "a", now, 0).is_ok());
+        assert!(limiter.check_at(&limits, "web", "a", now, 0).is_err());
+
+        limiter.refund_at(&limits, "web", "a", now, 0);
+        let usage = limiter.snapshot_at(&limits, now, 0);
+        assert_eq!(usage["channels"]["web"]["today"], 0);
+        assert_eq!(usage["senders"]["a"]["available"], 1);
+        assert!(limiter.check_at(&limits, "web", "a", now, 0).is_ok());
+    }
+
+    #[test]
+    fn overrides_inherit_unset_fields() {
+        let mut limits = limits(
+            Limit {
+                per_minute: Some(10),
+                burst: None,
+                daily: Some(100),
+            },
+            Limit::default(),
+        );
+        limits.channels.insert(
+            "voice".into(),
+            Limit {
+                per_minute: Some(60),
+                ..Limit::default()
+            },
+        );
+        let voice = limits.for_channel("voice");
+        assert_eq!(voice.per_minute, Some(60));
+        assert_eq!(voice.daily, Some(100));
+        assert_eq!(limits.for_channel("web").per_minute, Some(10));
+    }
+}
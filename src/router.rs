@@ -1,16 +1,54 @@
-use crate::handlers::{call_ended, chat, health, monitor, session_started};
+use crate::auth;
+use crate::handlers::{admin, call_ended, chat, health, jobs, monitor, session_started};
 use crate::state::AppState;
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post},
     Router,
 };
 
 pub fn build(state: AppState) -> Router {
-    Router::new()
-        .route("/health", get(health::health))
-        .route("/chat", post(chat::chat))
+    // /chat and /jobs authenticate inside the handlers, since the token
+    // also decides which channels the caller may use.
+    let notify = Router::new()
         .route("/call-ended", post(call_ended::call_ended))
         .route("/session-started", post(session_started::session_started))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_notify,
+        ));
+    let status = Router::new()
         .route("/api/status", get(monitor::status))
+        .route("/api/queue", get(monitor::queue))
+        .route("/api/voice-sessions", get(monitor::voice_sessions))
+        .route("/metrics", get(monitor::metrics))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_status,
+        ));
+    let admin = Router::new()
+        .route("/admin/reload", post(admin::reload))
+        .route("/admin/held", get(admin::held))
+        .route("/admin/held/{id}/approve", post(admin::approve))
+        .route("/admin/held/{id}/reject", post(admin::reject))
+        .route("/admin/queue/{id}", delete(admin::remove))
+        .route("/admin/queue/{id}/front", post(admin::front))
+        .route("/admin/queue/channels/{channel}/drain", post(admin::drain))
+        .route("/admin/callbacks", get(admin::callbacks))
+        .route("/admin/callbacks/{id}/replay", post(admin::replay_callback))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_admin,
+        ));
+
+    Router::new()
+        .route("/health", get(health::health))
+        .route("/chat", post(chat::chat))
+        .route("/chat/stream", post(chat::chat_stream))
+        .route("/jobs", post(jobs::submit))
+        .route("/jobs/{id}", get(jobs::get).delete(jobs::cancel))
+        .merge(notify)
+        .merge(status)
+        .merge(admin)
         .with_state(state)
 }
@@ -0,0 +1,365 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::callbacks::{self, Kind, Outgoing};
+use crate::config::Config;
+use crate::handlers::chat::CallbackConfig;
+use crate::metrics::METRICS;
+use crate::settings::Settings;
+use crate::trust::TrustLevel;
+use crate::webhook::Webhooks;
+
+/// A `[[routes]]` rule: where else a finished response goes. Every
+/// condition that is set must hold for the route to apply.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Route {
+    /// Shown in logs and metrics. Defaults to the route's position.
+    pub name: Option<String>,
+    /// Request channel is one of these.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Request channel is none of these.
+    #[serde(default)]
+    pub except_channels: Vec<String>,
+    /// Sender is one of these.
+    #[serde(default)]
+    pub senders: Vec<String>,
+    /// Trust level the request ran at.
+    pub trust: Option<TrustLevel>,
+    /// Response length in characters, inclusive.
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    /// Whether the sender has a voice call in progress.
+    pub active_call: Option<bool>,
+    /// Where to deliver the response.
+    pub to: Vec<Target>,
+    /// Sent to the caller instead of the response once every target on
+    /// this route has it. If a delivery fails, the caller gets the full
+    /// response.
+    pub reply: Option<String>,
+    /// Skip the routes after this one when it applies.
+    #[serde(default)]
+    pub stop: bool,
+}
+
+/// One delivery on a route, using the callback types.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// For `webhook`.
+    pub url: Option<String>,
+    /// For `file` and `exec`: the configured target name.
+    pub target: Option<String>,
+    /// For `discord`: channel to post in instead of the request's
+    /// `metadata.discord_channel_id`.
+    pub channel_id: Option<String>,
+}
+
+/// The built-in routing: a response to a sender who is on a voice call is
+/// also spoken into the call. The caller on the other channel still gets
+/// the full text, since the call hears a version adapted for speech.
+pub fn defaults() -> Vec<Route> {
+    vec![Route {
+        name: Some("active-call".into()),
+        channels: Vec::new(),
+        except_channels: vec!["voice".into()],
+        senders: Vec::new(),
+        trust: None,
+        min_length: None,
+        max_length: None,
+        active_call: Some(true),
+        to: vec![Target {
+            kind: "voice".into(),
+            url: None,
+            target: None,
+            channel_id: None,
+        }],
+        reply: None,
+        stop: false,
+    }]
+}
+
+/// Check `[[routes]]` against the callback types and configured targets.
+pub fn validate(config: &Config) -> Result<(), String> {
+    for (i, route) in config.routes.iter().enumerate() {
+        let at = |msg: String| format!("invalid routes[{i}]: {msg}");
+        if route.to.is_empty() {
+            return Err(at("needs at least one target in `to`".into()));
+        }
+        if let (Some(min), Some(max)) = (route.min_length, route.max_length) {
+            if min > max {
+                return Err(at("min_length is greater than max_length".into()));
+            }
+        }
+        for target in &route.to {
+            let name = &target.kind;
+            let Some(kind) = Kind::lookup(name) else {
+                return Err(at(format!("unknown target type '{name}'")));
+            };
+            let named = |entries: &dyn Fn(&str) -> bool| match &target.target {
+                Some(t) if entries(t) => Ok(()),
+                Some(t) => Err(at(format!("unknown {name} target '{t}'"))),
+                None => Err(at(format!("{name} target needs a target name"))),
+            };
+            match kind {
+                Kind::Webhook if target.url.is_none() => {
+                    return Err(at("webhook target needs a url".into()));
+                }
+                Kind::File => named(&|t| config.callback_files.contains_key(t))?,
+                Kind::Exec => named(&|t| config.callback_commands.contains_key(t))?,
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What a route is matched against.
+pub struct Facts<'a> {
+    pub channel: &'a str,
+    pub sender: &'a str,
+    pub trust: TrustLevel,
+    pub length: usize,
+    pub active_call: bool,
+}
+
+impl Route {
+    fn matches(&self, facts: &Facts) -> bool {
+        let listed = |list: &[String], value: &str| list.iter().any(|v| v == value);
+        (self.channels.is_empty() || listed(&self.channels, facts.channel))
+            && !listed(&self.except_channels, facts.channel)
+            && (self.senders.is_empty() || listed(&self.senders, facts.sender))
+            && self.trust.map_or(true, |t| t == facts.trust)
+            && self.min_length.map_or(true, |n| facts.length >= n)
+            && self.max_length.map_or(true, |n| facts.length <= n)
+            && self.active_call.map_or(true, |a| a == facts.active_call)
+    }
+}
+
+/// The routes that apply, in order, up to and including the first
+/// matching route with `stop`.
+pub fn matching<'a>(routes: &'a [Route], facts: &Facts) -> Vec<(usize, &'a Route)> {
+    let mut matched = Vec::new();
+    for (i, route) in routes.iter().enumerate() {
+        if route.matches(facts) {
+            matched.push((i, route));
+            if route.stop {
+                break;
+            }
+        }
+    }
+    matched
+}
+
+/// Deliver a response along every route that applies. Returns the reply
+/// for the caller if a route with `reply` delivered everywhere.
+///
+/// Routes with a reply are delivered before returning, since the caller's
+/// answer depends on them; the rest are delivered in the background.
+pub async fn apply(
+    settings: &Arc<Settings>,
+    out: &Outgoing,
+    trust: TrustLevel,
+    active_call: Option<&str>,
+    webhooks: &Webhooks,
+    client: &reqwest::Client,
+) -> Option<String> {
+    let facts = Facts {
+        channel: &out.channel,
+        sender: &out.sender,
+        trust,
+        length: out.response.chars().count(),
+        active_call: active_call.is_some(),
+    };
+    let mut reply = None;
+    for (i, route) in matching(&settings.config.routes, &facts) {
+        let name = route.name.clone().unwrap_or_else(|| format!("#{i}"));
+        info!(
+            "[{}] sender={} Response matched route {name}",
+            out.channel, out.sender
+        );
+        METRICS.routes.inc(&name);
+
+        let deliveries: Vec<_> = route
+            .to
+            .iter()
+            .map(|target| delivery(target, out, active_call))
+            .collect();
+        if route.reply.is_none() {
+            let (settings, webhooks, client) = (settings.clone(), webhooks.clone(), client.clone());
+            tokio::spawn(async move {
+                for (cb, out) in deliveries {
+                    callbacks::dispatch(&cb, out, &settings.config, &webhooks, &client).await;
+                }
+            });
+            continue;
+        }
+
+        let mut delivered = true;
+        for (cb, out) in deliveries {
+            delivered &= callbacks::dispatch(&cb, out, &settings.config, webhooks, client).await;
+        }
+        if delivered {
+            reply = reply.or_else(|| route.reply.clone());
+        } else {
+            warn!(
+                "[{}] Route {name} did not deliver everywhere, sending the full response to the caller",
+                out.channel
+            );
+        }
+    }
+    reply
+}
+
+/// The callback and response for one target. Voice targets speak into the
+/// sender's active call, or the request's own call; discord targets may
+/// name their own channel. Other targets get the request's metadata as is.
+fn delivery(
+    target: &Target,
+    out: &Outgoing,
+    active_call: Option<&str>,
+) -> (CallbackConfig, Outgoing) {
+    let mut out = out.clone();
+    if let (Some(Kind::Voice), Some(call_sid)) = (Kind::lookup(&target.kind), active_call) {
+        out.metadata.call_sid = Some(call_sid.to_string());
+    }
+    if let Some(channel_id) = &target.channel_id {
+        out.metadata.discord_channel_id = Some(channel_id.clone());
+    }
+    let cb = CallbackConfig {
+        callback_type: target.kind.clone(),
+        url: target.url.clone(),
+        target: target.target.clone(),
+    };
+    (cb, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts<'a>(channel: &'a str, length: usize, active_call: bool) -> Facts<'a> {
+        Facts {
+            channel,
+            sender: "D",
+            trust: TrustLevel::Verified,
+            length,
+            active_call,
+        }
+    }
+
+    fn routes(toml: &str) -> Vec<Route> {
+        #[derive(Deserialize)]
+        struct File {
+            routes: Vec<Route>,
+        }
+        toml::from_str::<File>(toml).unwrap().routes
+    }
+
+    fn names(matched: Vec<(usize, &Route)>) -> Vec<String> {
+        matched
+            .into_iter()
+            .map(|(_, r)| r.name.clone().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn default_route_follows_the_active_call() {
+        let routes = defaults();
+        assert_eq!(
+            names(matching(&routes, &facts("discord", 10, true))),
+            ["active-call"]
+        );
+        assert!(matching(&routes, &facts("discord", 10, false)).is_empty());
+        assert!(matching(&routes, &facts("voice", 10, true)).is_empty());
+    }
+
+    #[test]
+    fn conditions_combine_and_stop_ends_the_list() {
+        let routes = routes(
+            r#"
+            [[routes]]
+            name = "long-reflection"
+            channels = ["reflection"]
+            min_length = 500
+            to = [{ type = "discord", channel_id = "123" }]
+            stop = true
+
+            [[routes]]
+            name = "trusted"
+            trust = "trusted"
+            to = [{ type = "file", target = "log" }]
+
+            [[routes]]
+            name = "short"
+            max_length = 499
+            to = [{ type = "webhook", url = "http://localhost/hook" }]
+            "#,
+        );
+        let trusted = |channel, length| Facts {
+            trust: TrustLevel::Trusted,
+            ..facts(channel, length, false)
+        };
+        assert_eq!(
+            names(matching(&routes, &trusted("reflection", 800))),
+            ["long-reflection"]
+        );
+        assert_eq!(
+            names(matching(&routes, &trusted("reflection", 20))),
+            ["trusted", "short"]
+        );
+        assert_eq!(
+            names(matching(&routes, &facts("discord", 800, false))),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            names(matching(&routes, &facts("discord", 20, false))),
+            ["short"]
+        );
+    }
+
+    #[test]
+    fn targets_are_checked_against_config() {
+        let with = |toml: &str| Config {
+            routes: routes(toml),
+            ..Config::default()
+        };
+        assert!(validate(&with("[[routes]]\nto = [{ type = \"voice\" }]")).is_ok());
+        assert!(validate(&with("[[routes]]\nto = []")).is_err());
+        assert!(validate(&with("[[routes]]\nto = [{ type = \"pager\" }]")).is_err());
+        assert!(validate(&with("[[routes]]\nto = [{ type = \"webhook\" }]")).is_err());
+        let err = validate(&with(
+            "[[routes]]\nto = [{ type = \"exec\", target = \"say\" }]",
+        ))
+        .unwrap_err();
+        assert!(err.contains("routes[0]"), "{err}");
+    }
+
+    #[test]
+    fn voice_targets_use_the_active_call() {
+        let out = Outgoing {
+            request_id: 1,
+            channel: "discord".into(),
+            sender: "D".into(),
+            metadata: Default::default(),
+            response: "hi".into(),
+        };
+        let (cb, routed) = delivery(&defaults()[0].to[0], &out, Some("CA1"));
+        assert_eq!(cb.callback_type, "voice");
+        assert_eq!(routed.metadata.call_sid.as_deref(), Some("CA1"));
+
+        let webhook = Target {
+            kind: "webhook".into(),
+            url: Some("http://localhost/hook".into()),
+            target: None,
+            channel_id: None,
+        };
+        let (_, routed) = delivery(&webhook, &out, Some("CA1"));
+        assert_eq!(routed.metadata.call_sid, None);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::store::{self, SessionRecord, Store};
+
+/// What a Claude conversation is keyed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionScope {
+    /// One conversation per channel (default).
+    Channel,
+    /// One conversation per sender, shared across channels.
+    Sender,
+    /// One conversation per sender on each channel.
+    ChannelSender,
+    /// A single conversation shared by everything.
+    Global,
+}
+
+impl FromStr for SessionScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "channel" => Ok(Self::Channel),
+            "sender" => Ok(Self::Sender),
+            "channel+sender" | "channel_sender" => Ok(Self::ChannelSender),
+            "global" => Ok(Self::Global),
+            other => Err(format!(
+                "unknown session scope '{other}' (expected channel, sender, channel+sender or global)"
+            )),
+        }
+    }
+}
+
+struct Session {
+    id: String,
+    last_used: Instant,
+}
+
+/// Claude session ids keyed by [`SessionScope`]. Each key expires on its own
+/// after `ttl` of inactivity.
+#[derive(Clone)]
+pub struct SessionStore {
+    inner: Arc<Mutex<HashMap<String, Session>>>,
+    scope: SessionScope,
+    ttl: Duration,
+    store: Option<Store>,
+}
+
+impl SessionStore {
+    /// Create a session store, reloading unexpired sessions from `store` if
+    /// one is configured.
+    pub fn new(scope: SessionScope, ttl_secs: u64, store: Option<Store>) -> Self {
+        let ttl = Duration::from_secs(ttl_secs);
+        let mut sessions = HashMap::new();
+        if let Some(store) = &store {
+            for record in store.load_sessions() {
+                let last_used = store::instant_from_unix(record.last_used_unix);
+                if last_used.elapsed() <= ttl {
+                    sessions.insert(
+                        record.key,
+                        Session {
+                            id: record.session_id,
+                            last_used,
+                        },
+                    );
+                }
+            }
+            if !sessions.is_empty() {
+                info!("Restored {} Claude session(s)", sessions.len());
+            }
+        }
+        Self {
+            inner: Arc::new(Mutex::new(sessions)),
+            scope,
+            ttl,
+            store,
+        }
+    }
+
+    /// The session key a request on `channel` from `sender` belongs to.
+    pub fn key(&self, channel: &str, sender: &str) -> String {
+        match self.scope {
+            SessionScope::Channel => format!("channel:{channel}"),
+            SessionScope::Sender => format!("sender:{sender}"),
+            SessionScope::ChannelSender => format!("channel:{channel}/sender:{sender}"),
+            SessionScope::Global => "global".into(),
+        }
+    }
+
+    /// Current session id for `key`, dropping it if it has been idle too long.
+    pub async fn get(&self, key: &str) -> Option<String> {
+        let mut sessions = self.inner.lock().await;
+        match sessions.get(key) {
+            Some(s) if s.last_used.elapsed() > self.ttl => {
+                info!("Session {key} expired after idle timeout, starting fresh");
+                sessions.remove(key);
+                None
+            }
+            Some(s) => Some(s.id.clone()),
+            None => None,
+        }
+    }
+
+    /// Record activity on `key`, storing `session_id` if Claude returned one.
+    pub async fn update(&self, key: &str, session_id: Option<&str>) {
+        let mut sessions = self.inner.lock().await;
+        let session = match (sessions.get_mut(key), session_id) {
+            (Some(s), sid) => {
+                if let Some(sid) = sid {
+                    s.id = sid.to_string();
+                }
+                s.last_used = Instant::now();
+                s
+            }
+            (None, Some(sid)) => sessions.entry(key.to_string()).or_insert(Session {
+                id: sid.to_string(),
+                last_used: Instant::now(),
+            }),
+            (None, None) => return,
+        };
+
+        if let Some(store) = &self.store {
+            store.append_session(&SessionRecord {
+                key: key.to_string(),
+                session_id: session.id.clone(),
+                last_used_unix: store::now_unix(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scopes() {
+        assert_eq!("channel".parse(), Ok(SessionScope::Channel));
+        assert_eq!("sender".parse(), Ok(SessionScope::Sender));
+        assert_eq!("channel+sender".parse(), Ok(SessionScope::ChannelSender));
+        assert_eq!("global".parse(), Ok(SessionScope::Global));
+        assert!("nope".parse::<SessionScope>().is_err());
+    }
+
+    #[test]
+    fn keys_follow_scope() {
+        let by_channel = SessionStore::new(SessionScope::Channel, 60, None);
+        assert_eq!(by_channel.key("voice", "D"), "channel:voice");
+        assert_ne!(by_channel.key("voice", "D"), by_channel.key("phone", "D"));
+
+        let by_sender = SessionStore::new(SessionScope::Sender, 60, None);
+        assert_eq!(by_sender.key("voice", "D"), by_sender.key("discord", "D"));
+
+        let both = SessionStore::new(SessionScope::ChannelSender, 60, None);
+        assert_ne!(both.key("voice", "D"), both.key("voice", "E"));
+
+        let global = SessionStore::new(SessionScope::Global, 60, None);
+        assert_eq!(global.key("voice", "D"), global.key("phone", "E"));
+    }
+
+    #[tokio::test]
+    async fn keys_are_isolated() {
+        let store = SessionStore::new(SessionScope::Channel, 60, None);
+        store.update("channel:system", Some("abc")).await;
+        assert_eq!(store.get("channel:system").await.as_deref(), Some("abc"));
+        assert!(store.get("channel:phone").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_expire() {
+        let store = SessionStore::new(SessionScope::Channel, 0, None);
+        store.update("channel:voice", Some("abc")).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(store.get("channel:voice").await.is_none());
+    }
+}
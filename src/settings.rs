@@ -0,0 +1,82 @@
+use std::sync::{Arc, RwLock};
+
+use regex::RegexSet;
+use tracing::{info, warn};
+
+use crate::approval;
+use crate::config::Config;
+use crate::injection::InjectionDetector;
+
+/// Everything that can change on reload. Readers take a snapshot with
+/// [`SharedSettings::load`] and keep it for the whole request, so a reload
+/// never changes settings under an in-flight request.
+pub struct Settings {
+    pub config: Config,
+    pub detector: InjectionDetector,
+    /// Compiled `approval.tool_patterns`.
+    pub tool_requests: RegexSet,
+}
+
+impl Settings {
+    fn build(config: Config) -> Result<Self, String> {
+        let detector = InjectionDetector::with_patterns(
+            &config.injection_patterns,
+            config.injection_replace_defaults,
+        )?
+        .decode_payloads(config.injection_decode_payloads);
+        let tool_requests = approval::tool_patterns(&config.approval_tool_patterns)?;
+        Ok(Self {
+            config,
+            detector,
+            tool_requests,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct SharedSettings {
+    current: Arc<RwLock<Arc<Settings>>>,
+    /// Config file to re-read on reload.
+    path: Option<String>,
+}
+
+impl SharedSettings {
+    pub fn new(config: Config, path: Option<String>) -> Result<Self, String> {
+        Ok(Self {
+            current: Arc::new(RwLock::new(Arc::new(Settings::build(config)?))),
+            path,
+        })
+    }
+
+    /// The current settings snapshot.
+    pub fn load(&self) -> Arc<Settings> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Re-read the config file and environment and swap in the result.
+    /// Returns the list of changes; on error the running settings are kept.
+    pub fn reload(&self) -> Result<Vec<String>, String> {
+        let mut config = Config::load(self.path.as_deref())?;
+        let old = self.load();
+
+        for field in config.keep_restart_only(&old.config) {
+            warn!("Reload: {field} changed but only takes effect after a restart");
+        }
+
+        let changes = old.config.diff(&config);
+        let settings = Settings::build(config)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(settings);
+
+        if changes.is_empty() {
+            info!("Configuration reloaded, no changes");
+        } else {
+            for change in &changes {
+                info!("Configuration reloaded: {change}");
+            }
+        }
+        Ok(changes)
+    }
+}
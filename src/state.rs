@@ -1,30 +1,73 @@
+use crate::audit::AuditLog;
 use crate::config::Config;
-use crate::injection::InjectionDetector;
-use crate::queue::{self, Queue};
+use crate::jobs::JobStore;
+use crate::queue::{self, Queue, WorkerContext};
+use crate::ratelimit::RateLimiter;
+use crate::session::SessionStore;
+use crate::settings::SharedSettings;
+use crate::store::Store;
 use crate::tracker::RequestTracker;
-use crate::voice_session::VoiceSessionTracker;
+use crate::voice_session::{self, VoiceSessionTracker};
+use crate::webhook::{self, Webhooks};
+use tracing::info;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Config,
+    pub settings: SharedSettings,
     pub queue: Queue,
-    pub detector: InjectionDetector,
     pub tracker: RequestTracker,
     pub voice_sessions: VoiceSessionTracker,
+    pub sessions: SessionStore,
+    pub jobs: JobStore,
+    pub audit: Option<AuditLog>,
+    pub limiter: RateLimiter,
+    pub webhooks: Webhooks,
 }
 
 impl AppState {
-    pub fn new(config: Config) -> Self {
-        let detector = InjectionDetector::new();
-        let tracker = RequestTracker::new();
-        let voice_sessions = VoiceSessionTracker::new(config.voice_session_timeout_secs);
-        let queue = queue::spawn(config.clone(), tracker.clone(), voice_sessions.clone());
+    pub fn new(config: Config, config_path: Option<String>) -> Self {
+        let store = config.data_dir.as_deref().map(|dir| {
+            info!("Persisting state under {dir}");
+            Store::open(dir).expect("failed to open data directory")
+        });
+
+        let audit = config.audit_path().map(|dir| {
+            info!("Writing audit log to {}", dir.display());
+            AuditLog::open(dir, config.audit_max_bytes, config.audit_rotate_daily)
+                .expect("failed to open audit log")
+        });
+
+        let tracker = RequestTracker::new(store.clone());
+        let voice_sessions =
+            VoiceSessionTracker::new(config.voice_session_timeout_secs, store.clone());
+        let webhooks = Webhooks::new(store.clone());
+        let sessions = SessionStore::new(config.session_scope, config.session_ttl_secs, store);
+        let workers = config.workers;
+        let settings = SharedSettings::new(config, config_path)
+            .expect("injection patterns are validated at load");
+        webhook::spawn(webhooks.clone(), settings.clone());
+        voice_session::spawn(voice_sessions.clone());
+        let queue = queue::spawn(
+            workers,
+            WorkerContext {
+                settings: settings.clone(),
+                tracker: tracker.clone(),
+                voice_sessions: voice_sessions.clone(),
+                sessions: sessions.clone(),
+                audit: audit.clone(),
+                webhooks: webhooks.clone(),
+            },
+        );
         Self {
-            config,
+            settings,
             queue,
-            detector,
             tracker,
             voice_sessions,
+            sessions,
+            jobs: JobStore::new(),
+            audit,
+            limiter: RateLimiter::new(),
+            webhooks,
         }
     }
 }
@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::injection::Detection;
+use crate::tracker::Outcome;
+use crate::webhook::Delivery;
+
+const HISTORY_FILE: &str = "history.jsonl";
+const SESSIONS_FILE: &str = "sessions.jsonl";
+const VOICE_FILE: &str = "voice_sessions.jsonl";
+const CALLBACKS_FILE: &str = "callbacks.jsonl";
+
+/// One finished request, with the full message and response.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub id: u64,
+    pub channel: String,
+    pub sender: String,
+    pub message: String,
+    pub response: String,
+    pub started_unix: u64,
+    pub completed_unix: u64,
+    pub duration_secs: u64,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub injection: Option<Detection>,
+}
+
+/// Latest Claude session id for a session key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub key: String,
+    pub session_id: String,
+    pub last_used_unix: u64,
+}
+
+/// A change to the voice session map.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum VoiceRecord {
+    Touch {
+        sender: String,
+        call_sid: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transport: Option<String>,
+        /// Missing in files written before calls kept their start time.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        started_unix: Option<u64>,
+        last_activity_unix: u64,
+    },
+    Remove {
+        call_sid: String,
+    },
+}
+
+/// A change to the webhook delivery list.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum CallbackRecord {
+    /// Queued, or retried and waiting again.
+    Pending(Delivery),
+    /// Given up on after the last attempt.
+    Dead(Delivery),
+    Delivered {
+        id: u64,
+    },
+}
+
+/// Append-only JSONL files under the data directory. Each file is a log of
+/// records; loaders replay it and, for the session maps, rewrite it with
+/// only the surviving entries so it does not grow without bound.
+#[derive(Clone)]
+pub struct Store {
+    dir: PathBuf,
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl Store {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            write_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    pub fn append_history(&self, record: &HistoryRecord) {
+        self.append(HISTORY_FILE, record);
+    }
+
+    /// The most recent `limit` history records, oldest first, plus the
+    /// highest id ever recorded. Trims the file to those records; the one
+    /// holding the highest id is kept too so the id counter survives.
+    pub fn load_history(&self, limit: usize) -> (Vec<HistoryRecord>, Option<u64>) {
+        let mut recent: VecDeque<HistoryRecord> = VecDeque::with_capacity(limit);
+        let mut max_id = None;
+        let mut dropped: Option<HistoryRecord> = None;
+        for record in self.read::<HistoryRecord>(HISTORY_FILE) {
+            max_id = max_id.max(Some(record.id));
+            if recent.len() == limit {
+                if let Some(oldest) = recent.pop_front() {
+                    if dropped.as_ref().map_or(true, |d| oldest.id > d.id) {
+                        dropped = Some(oldest);
+                    }
+                }
+            }
+            recent.push_back(record);
+        }
+        let recent: Vec<_> = recent.into();
+        if let Some(dropped) = dropped {
+            let keep_dropped = recent.iter().all(|r| r.id < dropped.id);
+            let kept: Vec<_> = keep_dropped
+                .then_some(&dropped)
+                .into_iter()
+                .chain(&recent)
+                .collect();
+            self.rewrite(HISTORY_FILE, &kept);
+        }
+        (recent, max_id)
+    }
+
+    pub fn append_session(&self, record: &SessionRecord) {
+        self.append(SESSIONS_FILE, record);
+    }
+
+    /// Latest session per key, compacting the file.
+    pub fn load_sessions(&self) -> Vec<SessionRecord> {
+        let mut latest: HashMap<String, SessionRecord> = HashMap::new();
+        for record in self.read::<SessionRecord>(SESSIONS_FILE) {
+            latest.insert(record.key.clone(), record);
+        }
+        let records: Vec<_> = latest.into_values().collect();
+        self.rewrite(SESSIONS_FILE, &records);
+        records
+    }
+
+    pub fn append_voice(&self, record: &VoiceRecord) {
+        self.append(VOICE_FILE, record);
+    }
+
+    /// Voice sessions still present after replaying touches and removals,
+    /// compacting the file.
+    pub fn load_voice(&self) -> Vec<VoiceRecord> {
+        let mut live: HashMap<String, VoiceRecord> = HashMap::new();
+        for record in self.read::<VoiceRecord>(VOICE_FILE) {
+            match &record {
+                VoiceRecord::Touch { call_sid, .. } => {
+                    live.insert(call_sid.clone(), record);
+                }
+                VoiceRecord::Remove { call_sid } => {
+                    live.remove(call_sid);
+                }
+            }
+        }
+        let records: Vec<_> = live.into_values().collect();
+        self.rewrite(VOICE_FILE, &records);
+        records
+    }
+
+    pub fn append_callback(&self, record: &CallbackRecord) {
+        self.append(CALLBACKS_FILE, record);
+    }
+
+    /// Latest state of every undelivered webhook, compacting the file.
+    pub fn load_callbacks(&self) -> Vec<CallbackRecord> {
+        let mut live: BTreeMap<u64, CallbackRecord> = BTreeMap::new();
+        for record in self.read::<CallbackRecord>(CALLBACKS_FILE) {
+            match record {
+                CallbackRecord::Pending(ref d) | CallbackRecord::Dead(ref d) => {
+                    live.insert(d.id, record);
+                }
+                CallbackRecord::Delivered { id } => {
+                    live.remove(&id);
+                }
+            }
+        }
+        let records: Vec<_> = live.into_values().collect();
+        self.rewrite(CALLBACKS_FILE, &records);
+        records
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+
+    fn append<T: Serialize>(&self, file: &str, record: &T) {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let path = self.path(file);
+        let result = serde_json::to_string(record)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut f = OpenOptions::new().create(true).append(true).open(&path)?;
+                writeln!(f, "{line}")
+            });
+        if let Err(e) = result {
+            warn!("failed to append to {}: {e}", path.display());
+        }
+    }
+
+    fn read<T: DeserializeOwned>(&self, file: &str) -> Vec<T> {
+        let path = self.path(file);
+        let f = match File::open(&path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                warn!("failed to open {}: {e}", path.display());
+                return Vec::new();
+            }
+        };
+
+        let mut records = Vec::new();
+        for (n, line) in BufReader::new(f).lines().enumerate() {
+            let line = match line {
+                Ok(l) if l.trim().is_empty() => continue,
+                Ok(l) => l,
+                Err(e) => {
+                    warn!("failed to read {}: {e}", path.display());
+                    break;
+                }
+            };
+            match serde_json::from_str(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!("skipping bad record {}:{}: {e}", path.display(), n + 1),
+            }
+        }
+        records
+    }
+
+    fn rewrite<T: Serialize>(&self, file: &str, records: &[T]) {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let path = self.path(file);
+        if let Err(e) = write_atomic(&path, records) {
+            warn!("failed to compact {}: {e}", path.display());
+        }
+    }
+}
+
+fn write_atomic<T: Serialize>(path: &Path, records: &[T]) -> io::Result<()> {
+    let tmp = path.with_extension("jsonl.tmp");
+    {
+        let mut f = File::create(&tmp)?;
+        for record in records {
+            writeln!(f, "{}", serde_json::to_string(record)?)?;
+        }
+        f.sync_all()?;
+    }
+    fs::rename(tmp, path)
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Map a persisted unix timestamp back onto the monotonic clock.
+pub fn instant_from_unix(unix: u64) -> Instant {
+    let age = Duration::from_secs(now_unix().saturating_sub(unix));
+    Instant::now().checked_sub(age).unwrap_or_else(Instant::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> Store {
+        let dir =
+            std::env::temp_dir().join(format!("bridge-echo-store-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Store::open(dir).unwrap()
+    }
+
+    fn history(id: u64) -> HistoryRecord {
+        HistoryRecord {
+            id,
+            channel: "discord".into(),
+            sender: "D".into(),
+            message: format!("message {id}"),
+            response: format!("response {id}"),
+            started_unix: 1,
+            completed_unix: 2,
+            duration_secs: 1,
+            outcome: Outcome::Completed,
+            injection: None,
+        }
+    }
+
+    #[test]
+    fn history_keeps_most_recent() {
+        let store = temp_store("history");
+        for id in 0..10 {
+            store.append_history(&history(id));
+        }
+        let (recent, max_id) = store.load_history(3);
+        let ids: Vec<u64> = recent.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![7, 8, 9]);
+        assert_eq!(max_id, Some(9));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn history_file_is_trimmed_on_load() {
+        let store = temp_store("history-trim");
+        for id in [0, 1, 9, 2, 3, 4] {
+            store.append_history(&history(id));
+        }
+        let (recent, max_id) = store.load_history(3);
+        let ids: Vec<u64> = recent.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+        assert_eq!(max_id, Some(9));
+        let contents = fs::read_to_string(store.path(HISTORY_FILE)).unwrap();
+        assert_eq!(contents.lines().count(), 4);
+
+        let (recent, max_id) = store.load_history(3);
+        assert_eq!(recent.len(), 3);
+        assert_eq!(max_id, Some(9));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn sessions_compact_to_latest() {
+        let store = temp_store("sessions");
+        for (key, sid) in [("a", "1"), ("b", "2"), ("a", "3")] {
+            store.append_session(&SessionRecord {
+                key: key.into(),
+                session_id: sid.into(),
+                last_used_unix: now_unix(),
+            });
+        }
+        let mut sessions = store.load_sessions();
+        sessions.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].session_id, "3");
+
+        let contents = fs::read_to_string(store.path(SESSIONS_FILE)).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn voice_removals_replay() {
+        let store = temp_store("voice");
+        store.append_voice(&VoiceRecord::Touch {
+            sender: "D".into(),
+            call_sid: "CA1".into(),
+            transport: None,
+            started_unix: None,
+            last_activity_unix: now_unix(),
+        });
+        store.append_voice(&VoiceRecord::Touch {
+            sender: "E".into(),
+            call_sid: "CA2".into(),
+            transport: Some("twilio".into()),
+            started_unix: Some(now_unix()),
+            last_activity_unix: now_unix(),
+        });
+        store.append_voice(&VoiceRecord::Touch {
+            sender: "D".into(),
+            call_sid: "CA3".into(),
+            transport: None,
+            started_unix: None,
+            last_activity_unix: now_unix(),
+        });
+        store.append_voice(&VoiceRecord::Remove {
+            call_sid: "CA1".into(),
+        });
+        let mut live: Vec<_> = store
+            .load_voice()
+            .into_iter()
+            .filter_map(|r| match r {
+                VoiceRecord::Touch {
+                    sender, call_sid, ..
+                } => Some((sender, call_sid)),
+                VoiceRecord::Remove { .. } => None,
+            })
+            .collect();
+        live.sort();
+        assert_eq!(
+            live,
+            [("D".into(), "CA3".into()), ("E".into(), "CA2".into())]
+        );
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn delivered_callbacks_drop_out() {
+        let store = temp_store("callbacks");
+        let delivery = |id, attempts| Delivery {
+            id,
+            request_id: id,
+            url: "http://localhost/hook".into(),
+            payload: serde_json::json!({"response": "hi"}),
+            attempts,
+            created_unix: now_unix(),
+            next_attempt_unix: now_unix(),
+            last_error: None,
+        };
+        store.append_callback(&CallbackRecord::Pending(delivery(1, 0)));
+        store.append_callback(&CallbackRecord::Pending(delivery(2, 0)));
+        store.append_callback(&CallbackRecord::Pending(delivery(1, 1)));
+        store.append_callback(&CallbackRecord::Dead(delivery(2, 8)));
+        store.append_callback(&CallbackRecord::Pending(delivery(3, 0)));
+        store.append_callback(&CallbackRecord::Delivered { id: 3 });
+
+        let live = store.load_callbacks();
+        assert_eq!(live.len(), 2);
+        assert!(matches!(&live[0], CallbackRecord::Pending(d) if d.attempts == 1));
+        assert!(matches!(&live[1], CallbackRecord::Dead(d) if d.id == 2));
+        let contents = fs::read_to_string(store.path(CALLBACKS_FILE)).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let store = temp_store("bad");
+        store.append_history(&history(1));
+        fs::OpenOptions::new()
+            .append(true)
+            .open(store.path(HISTORY_FILE))
+            .unwrap()
+            .write_all(b"{not json\n")
+            .unwrap();
+        store.append_history(&history(2));
+        let (recent, _) = store.load_history(10);
+        assert_eq!(recent.len(), 2);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
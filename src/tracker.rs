@@ -1,16 +1,26 @@
 use std::sync::Arc;
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{RwLock, RwLockWriteGuard};
+
+use crate::injection::Detection;
+use crate::queue::{Priority, QueuedRequest};
+use crate::store::{self, HistoryRecord, Store};
 
 #[derive(Clone, Debug)]
 pub struct ActiveRequest {
     pub id: u64,
     pub channel: String,
     pub sender: String,
+    pub message: String,
     pub message_preview: String,
+    pub priority: Priority,
+    /// Worker slot processing this request.
+    pub worker: usize,
     pub started_at: Instant,
     pub started_unix: u64,
     pub alerts_sent: Vec<u64>,
+    /// Injection scan of the message, if anything matched.
+    pub injection: Option<Detection>,
 }
 
 #[derive(Clone, Debug, serde::Serialize)]
@@ -18,8 +28,58 @@ pub struct ActiveSnapshot {
     pub id: u64,
     pub channel: String,
     pub message_preview: String,
+    pub priority: Priority,
+    pub worker: usize,
     pub started_unix: u64,
     pub elapsed_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub injection: Option<Detection>,
+}
+
+/// How a request finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Completed,
+    Failed,
+    TimedOut,
+    Cancelled,
+    /// Refused by the injection policy without running.
+    Blocked,
+    /// Held for approval and then rejected, or not approved in time.
+    Rejected,
+}
+
+/// A request waiting for an admin to approve or reject it.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct HeldRequest {
+    pub id: u64,
+    pub channel: String,
+    pub sender: String,
+    pub message: String,
+    pub message_preview: String,
+    pub priority: Priority,
+    /// Why the request was held.
+    pub reason: String,
+    pub held_unix: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub injection: Option<Detection>,
+}
+
+impl HeldRequest {
+    pub fn new(req: &QueuedRequest, reason: String) -> Self {
+        Self {
+            id: req.id,
+            channel: req.channel.clone(),
+            sender: req.sender.clone(),
+            message: req.original_message.clone(),
+            message_preview: preview(&req.original_message),
+            priority: req.priority,
+            reason,
+            held_unix: store::now_unix(),
+            injection: req.injection.is_detected().then(|| req.injection.clone()),
+        }
+    }
 }
 
 #[derive(Clone, Debug, serde::Serialize)]
@@ -31,6 +91,25 @@ pub struct CompletedRequest {
     pub started_unix: u64,
     pub completed_unix: u64,
     pub duration_secs: u64,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub injection: Option<Detection>,
+}
+
+impl From<&HistoryRecord> for CompletedRequest {
+    fn from(r: &HistoryRecord) -> Self {
+        Self {
+            id: r.id,
+            channel: r.channel.clone(),
+            message_preview: preview(&r.message),
+            response_preview: preview(&r.response),
+            started_unix: r.started_unix,
+            completed_unix: r.completed_unix,
+            duration_secs: r.duration_secs,
+            outcome: r.outcome,
+            injection: r.injection.clone(),
+        }
+    }
 }
 
 const MAX_COMPLETED: usize = 50;
@@ -39,48 +118,85 @@ const MAX_COMPLETED: usize = 50;
 struct Inner {
     next_id: u64,
     active: Vec<ActiveRequest>,
+    held: Vec<HeldRequest>,
     completed: Vec<CompletedRequest>,
 }
 
 #[derive(Clone)]
 pub struct RequestTracker {
     inner: Arc<RwLock<Inner>>,
+    store: Option<Store>,
 }
 
 impl RequestTracker {
-    pub fn new() -> Self {
+    /// Create a tracker, reloading recent history and the id counter from
+    /// `store` if one is configured.
+    pub fn new(store: Option<Store>) -> Self {
+        let mut inner = Inner::default();
+        if let Some(store) = &store {
+            let (history, max_id) = store.load_history(MAX_COMPLETED);
+            inner.next_id = max_id.map_or(0, |id| id + 1);
+            inner.completed = history.iter().map(CompletedRequest::from).collect();
+        }
         Self {
-            inner: Arc::new(RwLock::new(Inner::default())),
+            inner: Arc::new(RwLock::new(inner)),
+            store,
         }
     }
 
-    pub async fn start(&self, channel: &str, sender: &str, message: &str) -> u64 {
+    /// Reserve the id for a new request. Ids are assigned at submission so
+    /// callers can refer to a request while it is still queued.
+    pub async fn next_id(&self) -> u64 {
         let mut inner = self.inner.write().await;
         let id = inner.next_id;
         inner.next_id += 1;
+        id
+    }
 
-        let preview = if message.len() > 80 {
-            format!("{}...", &message[..80])
-        } else {
-            message.to_string()
-        };
+    pub async fn start(&self, req: &QueuedRequest, worker: usize) {
+        let mut inner = self.inner.write().await;
 
-        let now_unix = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        let now_unix = store::now_unix();
 
         inner.active.push(ActiveRequest {
-            id,
-            channel: channel.to_string(),
-            sender: sender.to_string(),
-            message_preview: preview,
+            id: req.id,
+            channel: req.channel.clone(),
+            sender: req.sender.clone(),
+            message: req.original_message.clone(),
+            message_preview: preview(&req.original_message),
+            priority: req.priority,
+            worker,
             started_at: Instant::now(),
             started_unix: now_unix,
             alerts_sent: Vec::new(),
+            injection: req.injection.is_detected().then(|| req.injection.clone()),
         });
+    }
 
-        id
+    /// Mark a request as waiting for approval.
+    pub async fn hold(&self, held: HeldRequest) {
+        self.inner.write().await.held.push(held);
+    }
+
+    /// Clear a request's pending-approval state, returning it if it was held.
+    pub async fn unhold(&self, id: u64) -> Option<HeldRequest> {
+        let mut inner = self.inner.write().await;
+        let pos = inner.held.iter().position(|r| r.id == id)?;
+        Some(inner.held.remove(pos))
+    }
+
+    pub async fn is_held(&self, id: u64) -> bool {
+        let inner = self.inner.read().await;
+        inner.held.iter().any(|r| r.id == id)
+    }
+
+    pub async fn held_snapshot(&self) -> Vec<HeldRequest> {
+        self.inner.read().await.held.clone()
+    }
+
+    pub async fn is_active(&self, id: u64) -> bool {
+        let inner = self.inner.read().await;
+        inner.active.iter().any(|r| r.id == id)
     }
 
     /// Check if a sender has an active request on a different channel.
@@ -93,39 +209,45 @@ impl RequestTracker {
             .any(|r| r.sender == sender && r.channel != channel)
     }
 
-    pub async fn complete(&self, id: u64, response: &str) {
+    pub async fn complete(&self, id: u64, response: &str, outcome: Outcome) {
         let mut inner = self.inner.write().await;
 
         let pos = inner.active.iter().position(|r| r.id == id);
         let Some(pos) = pos else { return };
         let req = inner.active.remove(pos);
 
-        let duration = req.started_at.elapsed().as_secs();
-        let now_unix = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        let response_preview = if response.len() > 80 {
-            format!("{}...", &response[..80])
-        } else {
-            response.to_string()
-        };
-
-        inner.completed.push(CompletedRequest {
+        let record = HistoryRecord {
             id,
             channel: req.channel,
-            message_preview: req.message_preview,
-            response_preview,
+            sender: req.sender,
+            message: req.message,
+            response: response.to_string(),
             started_unix: req.started_unix,
-            completed_unix: now_unix,
-            duration_secs: duration,
-        });
+            completed_unix: store::now_unix(),
+            duration_secs: req.started_at.elapsed().as_secs(),
+            outcome,
+            injection: req.injection,
+        };
+        self.finish(inner, record);
+    }
+
+    /// Record a request that was settled without running, such as one
+    /// blocked by the injection policy.
+    pub async fn record(&self, record: HistoryRecord) {
+        self.finish(self.inner.write().await, record);
+    }
 
+    fn finish(&self, mut inner: RwLockWriteGuard<'_, Inner>, record: HistoryRecord) {
+        inner.completed.push(CompletedRequest::from(&record));
         if inner.completed.len() > MAX_COMPLETED {
             let drain = inner.completed.len() - MAX_COMPLETED;
             inner.completed.drain(..drain);
         }
+        drop(inner);
+
+        if let Some(store) = &self.store {
+            store.append_history(&record);
+        }
     }
 
     pub async fn active_snapshot(&self) -> Vec<ActiveSnapshot> {
@@ -137,8 +259,11 @@ impl RequestTracker {
                 id: r.id,
                 channel: r.channel.clone(),
                 message_preview: r.message_preview.clone(),
+                priority: r.priority,
+                worker: r.worker,
                 started_unix: r.started_unix,
                 elapsed_secs: r.started_at.elapsed().as_secs(),
+                injection: r.injection.clone(),
             })
             .collect()
     }
@@ -174,3 +299,16 @@ impl RequestTracker {
             .collect()
     }
 }
+
+/// First 80 bytes of `text` (at a char boundary), with an ellipsis if cut.
+pub fn preview(text: &str) -> String {
+    if text.len() > 80 {
+        let mut end = 80;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &text[..end])
+    } else {
+        text.to_string()
+    }
+}
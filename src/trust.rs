@@ -1,15 +1,229 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TrustLevel {
     Trusted,
     Verified,
     Untrusted,
 }
 
-pub fn channel_trust(channel: &str) -> TrustLevel {
-    match channel {
-        "reflection" | "system" => TrustLevel::Trusted,
-        "discord" | "discord-echo" | "voice" => TrustLevel::Verified,
-        _ => TrustLevel::Untrusted,
+/// A channel definition from the `[channels.<name>]` config table.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    pub trust: TrustLevel,
+    /// Sender assumed when a request on this channel does not name one.
+    #[serde(default)]
+    pub default_sender: Option<String>,
+}
+
+/// Channel → trust mapping. Channels not listed are untrusted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustMap {
+    channels: BTreeMap<String, ChannelConfig>,
+}
+
+impl Default for TrustMap {
+    fn default() -> Self {
+        let mut map = Self {
+            channels: BTreeMap::new(),
+        };
+        for name in ["reflection", "system"] {
+            map.insert(name, TrustLevel::Trusted, None);
+        }
+        for name in ["discord", "discord-echo", "voice"] {
+            map.insert(name, TrustLevel::Verified, Some("D"));
+        }
+        map
+    }
+}
+
+impl TrustMap {
+    fn insert(&mut self, name: &str, trust: TrustLevel, default_sender: Option<&str>) {
+        self.channels.insert(
+            name.to_string(),
+            ChannelConfig {
+                trust,
+                default_sender: default_sender.map(String::from),
+            },
+        );
+    }
+
+    /// Add or replace channel definitions.
+    pub fn extend(&mut self, channels: BTreeMap<String, ChannelConfig>) {
+        self.channels.extend(channels);
+    }
+
+    /// Per-channel differences from `self` to `new`.
+    pub fn diff(&self, new: &TrustMap) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (name, old) in &self.channels {
+            match new.channels.get(name) {
+                None => changes.push(format!("channels.{name}: removed")),
+                Some(c) if c != old => {
+                    changes.push(format!("channels.{name}: {old:?} -> {c:?}"));
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, c) in &new.channels {
+            if !self.channels.contains_key(name) {
+                changes.push(format!("channels.{name}: added {c:?}"));
+            }
+        }
+        changes
+    }
+
+    pub fn trust(&self, channel: &str) -> TrustLevel {
+        self.channels
+            .get(channel)
+            .map_or(TrustLevel::Untrusted, |c| c.trust)
+    }
+
+    pub fn default_sender(&self, channel: &str) -> Option<&str> {
+        self.channels
+            .get(channel)
+            .and_then(|c| c.default_sender.as_deref())
+    }
+}
+
+/// Claude CLI `--permission-mode` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionMode {
+    /// Tools that need approval are refused, since nobody can approve them
+    /// in print mode.
+    Default,
+    AcceptEdits,
+    Plan,
+    /// No permission checks (`--dangerously-skip-permissions`).
+    BypassPermissions,
+}
+
+impl PermissionMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::AcceptEdits => "acceptEdits",
+            Self::Plan => "plan",
+            Self::BypassPermissions => "bypassPermissions",
+        }
+    }
+}
+
+/// What the Claude subprocess may do for requests at one trust level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolPolicy {
+    pub mode: PermissionMode,
+    /// Passed as `--allowedTools` when non-empty.
+    pub allowed_tools: Vec<String>,
+    /// Passed as `--disallowedTools` when non-empty. Wins over everything
+    /// else, including `bypassPermissions`.
+    pub disallowed_tools: Vec<String>,
+    /// Working directory for Claude. Defaults to the configured home.
+    pub workdir: Option<String>,
+}
+
+/// Tools that run commands, change files or read local data.
+const RESTRICTED_TOOLS: &[&str] = &[
+    "Bash",
+    "Edit",
+    "MultiEdit",
+    "Write",
+    "NotebookEdit",
+    "Read",
+    "Glob",
+    "Grep",
+    "LS",
+    "Task",
+];
+
+/// Every built-in tool, for runs that may not use any.
+const ALL_TOOLS: &[&str] = &[
+    "Bash",
+    "BashOutput",
+    "KillShell",
+    "Edit",
+    "MultiEdit",
+    "Write",
+    "NotebookEdit",
+    "NotebookRead",
+    "Read",
+    "Glob",
+    "Grep",
+    "LS",
+    "Task",
+    "TodoWrite",
+    "WebFetch",
+    "WebSearch",
+    "SlashCommand",
+    "ExitPlanMode",
+];
+
+/// A [`ToolPolicy`] for each trust level, from the `[permissions.<level>]`
+/// config tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permissions {
+    pub trusted: ToolPolicy,
+    pub verified: ToolPolicy,
+    pub untrusted: ToolPolicy,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        let unrestricted = ToolPolicy {
+            mode: PermissionMode::BypassPermissions,
+            allowed_tools: Vec::new(),
+            disallowed_tools: Vec::new(),
+            workdir: None,
+        };
+        Self {
+            trusted: unrestricted.clone(),
+            verified: unrestricted,
+            untrusted: ToolPolicy {
+                mode: PermissionMode::Default,
+                allowed_tools: Vec::new(),
+                disallowed_tools: RESTRICTED_TOOLS.iter().map(|t| t.to_string()).collect(),
+                workdir: Some(
+                    std::env::temp_dir()
+                        .join("bridge-echo-untrusted")
+                        .to_string_lossy()
+                        .into_owned(),
+                ),
+            },
+        }
+    }
+}
+
+impl Permissions {
+    pub fn for_level(&self, level: TrustLevel) -> &ToolPolicy {
+        match level {
+            TrustLevel::Trusted => &self.trusted,
+            TrustLevel::Verified => &self.verified,
+            TrustLevel::Untrusted => &self.untrusted,
+        }
+    }
+
+    pub fn for_level_mut(&mut self, level: TrustLevel) -> &mut ToolPolicy {
+        match level {
+            TrustLevel::Trusted => &mut self.trusted,
+            TrustLevel::Verified => &mut self.verified,
+            TrustLevel::Untrusted => &mut self.untrusted,
+        }
+    }
+
+    /// The untrusted policy with every tool disabled, for requests the
+    /// injection policy downgrades.
+    pub fn downgraded(&self) -> ToolPolicy {
+        ToolPolicy {
+            mode: PermissionMode::Default,
+            allowed_tools: Vec::new(),
+            disallowed_tools: ALL_TOOLS.iter().map(|t| t.to_string()).collect(),
+            workdir: self.untrusted.workdir.clone(),
+        }
     }
 }
 
@@ -39,6 +253,10 @@ pub fn trust_context(channel: &str, level: TrustLevel) -> String {
 mod tests {
     use super::*;
 
+    fn channel_trust(channel: &str) -> TrustLevel {
+        TrustMap::default().trust(channel)
+    }
+
     #[test]
     fn trusted_channels() {
         assert_eq!(channel_trust("reflection"), TrustLevel::Trusted);
@@ -59,6 +277,43 @@ mod tests {
         assert_eq!(channel_trust(""), TrustLevel::Untrusted);
     }
 
+    #[test]
+    fn configured_channels_override_defaults() {
+        let mut map = TrustMap::default();
+        map.extend(BTreeMap::from([
+            (
+                "slack".to_string(),
+                ChannelConfig {
+                    trust: TrustLevel::Verified,
+                    default_sender: Some("S".into()),
+                },
+            ),
+            (
+                "voice".to_string(),
+                ChannelConfig {
+                    trust: TrustLevel::Untrusted,
+                    default_sender: None,
+                },
+            ),
+        ]));
+        assert_eq!(map.trust("slack"), TrustLevel::Verified);
+        assert_eq!(map.default_sender("slack"), Some("S"));
+        assert_eq!(map.trust("voice"), TrustLevel::Untrusted);
+        assert_eq!(map.trust("discord"), TrustLevel::Verified);
+    }
+
+    #[test]
+    fn untrusted_policy_blocks_shell_and_edits() {
+        let policy = Permissions::default()
+            .for_level(TrustLevel::Untrusted)
+            .clone();
+        assert_ne!(policy.mode, PermissionMode::BypassPermissions);
+        for tool in ["Bash", "Edit", "Write"] {
+            assert!(policy.disallowed_tools.iter().any(|t| t == tool), "{tool}");
+        }
+        assert!(policy.workdir.is_some());
+    }
+
     #[test]
     fn context_contains_channel_name() {
         let ctx = trust_context("slack", TrustLevel::Verified);
@@ -77,4 +332,15 @@ mod tests {
         let ctx = trust_context("phone", TrustLevel::Untrusted);
         assert!(ctx.contains("Do NOT execute"));
     }
+
+    #[test]
+    fn downgraded_policy_disables_every_tool() {
+        let permissions = Permissions::default();
+        let policy = permissions.downgraded();
+        assert_eq!(policy.mode, PermissionMode::Default);
+        for tool in ["Bash", "Read", "WebFetch", "Task"] {
+            assert!(policy.disallowed_tools.iter().any(|t| t == tool), "{tool}");
+        }
+        assert_eq!(policy.workdir, permissions.untrusted.workdir);
+    }
 }
@@ -1,66 +1,238 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use serde::Serialize;
 use tokio::sync::RwLock;
+use tracing::info;
+
+use crate::store::{self, Store, VoiceRecord};
 
 /// Tracks active voice calls so bridge-echo can route cross-channel
 /// responses to voice instead of the originating channel.
 ///
-/// A voice session is created when a voice-channel request arrives with
-/// a call_sid, and cleared when voice-echo notifies that the call ended
-/// or the session times out from inactivity.
+/// A voice session is created when voice-echo reports a new call or a
+/// voice-channel request arrives with a call_sid, and cleared when
+/// voice-echo notifies that the call ended or the sweeper finds it idle
+/// past the timeout. A sender may have several calls at once.
 #[derive(Clone)]
 pub struct VoiceSessionTracker {
+    /// Keyed by call_sid.
     inner: Arc<RwLock<HashMap<String, VoiceSession>>>,
     timeout_secs: u64,
+    store: Option<Store>,
 }
 
 struct VoiceSession {
-    /// The call_sid from Twilio.
-    call_sid: String,
+    sender: String,
+    /// How the call is connected, as reported by voice-echo.
+    transport: Option<String>,
+    started_unix: u64,
     /// Last time a voice request came through for this session.
     last_activity: Instant,
 }
 
+/// An active call, as listed by `GET /api/voice-sessions`.
+#[derive(Debug, Serialize)]
+pub struct VoiceCall {
+    pub call_sid: String,
+    pub sender: String,
+    pub transport: Option<String>,
+    pub started_unix: u64,
+    pub idle_secs: u64,
+    /// Seconds of inactivity left before the call is considered over.
+    pub expires_in_secs: u64,
+}
+
+impl VoiceSession {
+    fn expired(&self, timeout_secs: u64) -> bool {
+        self.last_activity.elapsed().as_secs() >= timeout_secs
+    }
+}
+
 impl VoiceSessionTracker {
-    pub fn new(timeout_secs: u64) -> Self {
+    /// Create a tracker, reloading voice sessions from `store` if one is
+    /// configured.
+    pub fn new(timeout_secs: u64, store: Option<Store>) -> Self {
+        let mut sessions = HashMap::new();
+        if let Some(store) = &store {
+            for record in store.load_voice() {
+                if let VoiceRecord::Touch {
+                    sender,
+                    call_sid,
+                    transport,
+                    started_unix,
+                    last_activity_unix,
+                } = record
+                {
+                    sessions.insert(
+                        call_sid,
+                        VoiceSession {
+                            sender,
+                            transport,
+                            started_unix: started_unix.unwrap_or(last_activity_unix),
+                            last_activity: store::instant_from_unix(last_activity_unix),
+                        },
+                    );
+                }
+            }
+        }
         Self {
-            inner: Arc::new(RwLock::new(HashMap::new())),
+            inner: Arc::new(RwLock::new(sessions)),
             timeout_secs,
+            store,
         }
     }
 
-    /// Register or refresh a voice session.
-    /// Called when a voice-channel request arrives with a call_sid.
-    pub async fn touch(&self, sender: &str, call_sid: &str) {
+    /// Register or refresh a voice session. Called when voice-echo reports
+    /// a new call, with its transport, and when a voice-channel request
+    /// arrives with a call_sid.
+    pub async fn touch(&self, sender: &str, call_sid: &str, transport: Option<&str>) {
         let mut sessions = self.inner.write().await;
+        let now = store::now_unix();
         let entry = sessions
-            .entry(sender.to_string())
+            .entry(call_sid.to_string())
             .or_insert_with(|| VoiceSession {
-                call_sid: call_sid.to_string(),
+                sender: sender.to_string(),
+                transport: None,
+                started_unix: now,
                 last_activity: Instant::now(),
             });
-        entry.call_sid = call_sid.to_string();
+        entry.sender = sender.to_string();
+        if let Some(transport) = transport {
+            entry.transport = Some(transport.to_string());
+        }
         entry.last_activity = Instant::now();
+
+        if let Some(store) = &self.store {
+            store.append_voice(&VoiceRecord::Touch {
+                sender: sender.to_string(),
+                call_sid: call_sid.to_string(),
+                transport: entry.transport.clone(),
+                started_unix: Some(entry.started_unix),
+                last_activity_unix: now,
+            });
+        }
     }
 
     /// Remove a voice session. Called when voice-echo notifies call ended.
     pub async fn remove(&self, call_sid: &str) {
         let mut sessions = self.inner.write().await;
-        sessions.retain(|_, v| v.call_sid != *call_sid);
+        sessions.remove(call_sid);
+        self.persist_removal(call_sid);
     }
 
-    /// Check if a sender has an active (non-expired) voice session.
-    /// Returns the call_sid if active.
+    /// The sender's most recently active call, if any has not expired.
     pub async fn active_call_sid(&self, sender: &str) -> Option<String> {
         let sessions = self.inner.read().await;
-        sessions.get(sender).and_then(|s| {
-            if s.last_activity.elapsed().as_secs() < self.timeout_secs {
-                Some(s.call_sid.clone())
-            } else {
-                None
+        sessions
+            .iter()
+            .filter(|(_, s)| s.sender == sender && !s.expired(self.timeout_secs))
+            .max_by_key(|(_, s)| s.last_activity)
+            .map(|(call_sid, _)| call_sid.clone())
+    }
+
+    /// Calls that have not expired, oldest first.
+    pub async fn active(&self) -> Vec<VoiceCall> {
+        let sessions = self.inner.read().await;
+        let mut calls: Vec<_> = sessions
+            .iter()
+            .filter(|(_, s)| !s.expired(self.timeout_secs))
+            .map(|(call_sid, s)| {
+                let idle_secs = s.last_activity.elapsed().as_secs();
+                VoiceCall {
+                    call_sid: call_sid.clone(),
+                    sender: s.sender.clone(),
+                    transport: s.transport.clone(),
+                    started_unix: s.started_unix,
+                    idle_secs,
+                    expires_in_secs: self.timeout_secs.saturating_sub(idle_secs),
+                }
+            })
+            .collect();
+        calls.sort_by(|a, b| (a.started_unix, &a.call_sid).cmp(&(b.started_unix, &b.call_sid)));
+        calls
+    }
+
+    /// Drop every expired session, returning how many were dropped.
+    pub async fn sweep(&self) -> usize {
+        let mut sessions = self.inner.write().await;
+        let expired: Vec<String> = sessions
+            .iter()
+            .filter(|(_, s)| s.expired(self.timeout_secs))
+            .map(|(call_sid, _)| call_sid.clone())
+            .collect();
+        for call_sid in &expired {
+            if let Some(session) = sessions.remove(call_sid) {
+                info!(
+                    call_sid = %call_sid,
+                    sender = %session.sender,
+                    "Voice session expired"
+                );
             }
-        })
+            self.persist_removal(call_sid);
+        }
+        expired.len()
+    }
+
+    fn persist_removal(&self, call_sid: &str) {
+        if let Some(store) = &self.store {
+            store.append_voice(&VoiceRecord::Remove {
+                call_sid: call_sid.to_string(),
+            });
+        }
+    }
+}
+
+/// Start the sweeper, which evicts expired voice sessions every half
+/// timeout (at most once a minute).
+pub fn spawn(tracker: VoiceSessionTracker) {
+    let every = Duration::from_secs((tracker.timeout_secs / 2).clamp(1, 60));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            tracker.sweep().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn senders_can_hold_several_calls() {
+        let tracker = VoiceSessionTracker::new(300, None);
+        tracker.touch("D", "CA1", Some("twilio")).await;
+        tracker.touch("D", "CA2", Some("webrtc")).await;
+        tracker.touch("E", "CA3", None).await;
+        tracker.touch("D", "CA2", None).await;
+
+        assert_eq!(tracker.active_call_sid("D").await.as_deref(), Some("CA2"));
+        let calls = tracker.active().await;
+        assert_eq!(calls.len(), 3);
+        let ca2 = calls.iter().find(|c| c.call_sid == "CA2").unwrap();
+        assert_eq!(ca2.transport.as_deref(), Some("webrtc"));
+        assert_eq!(ca2.sender, "D");
+
+        tracker.remove("CA2").await;
+        assert_eq!(tracker.active_call_sid("D").await.as_deref(), Some("CA1"));
+        assert_eq!(tracker.active_call_sid("F").await, None);
+    }
+
+    #[tokio::test]
+    async fn sweep_evicts_expired_sessions() {
+        let expired = VoiceSessionTracker::new(0, None);
+        expired.touch("D", "CA1", None).await;
+        assert_eq!(expired.active_call_sid("D").await, None);
+        assert!(expired.active().await.is_empty());
+        assert_eq!(expired.sweep().await, 1);
+        assert_eq!(expired.inner.read().await.len(), 0);
+
+        let live = VoiceSessionTracker::new(300, None);
+        live.touch("D", "CA1", None).await;
+        assert_eq!(live.sweep().await, 0);
+        assert_eq!(live.active().await[0].expires_in_secs, 300);
     }
 }
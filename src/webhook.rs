@@ -0,0 +1,348 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::audit;
+use crate::config::Config;
+use crate::metrics::METRICS;
+use crate::settings::SharedSettings;
+use crate::store::{self, CallbackRecord, Store};
+
+/// `sha256=<hex HMAC of the body>`, sent when `callbacks.secret` is set.
+pub const SIGNATURE_HEADER: &str = "X-Bridge-Echo-Signature";
+/// Delivery id, the same on every retry so receivers can deduplicate.
+pub const DELIVERY_HEADER: &str = "X-Bridge-Echo-Delivery";
+
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A webhook callback waiting to be delivered, or given up on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: u64,
+    /// Request whose response this carries.
+    pub request_id: u64,
+    pub url: String,
+    pub payload: serde_json::Value,
+    /// Failed attempts so far.
+    pub attempts: u32,
+    pub created_unix: u64,
+    pub next_attempt_unix: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    pending: Vec<Delivery>,
+    dead: Vec<Delivery>,
+}
+
+/// Webhook callbacks still to deliver, retried with exponential backoff
+/// until they succeed or run out of attempts, then kept as dead letters
+/// until replayed. Every change is written to the store when one is
+/// configured, so undelivered callbacks survive a restart.
+#[derive(Clone)]
+pub struct Webhooks {
+    inner: Arc<Mutex<Inner>>,
+    notify: Arc<Notify>,
+    store: Option<Store>,
+}
+
+impl Webhooks {
+    pub fn new(store: Option<Store>) -> Self {
+        let mut inner = Inner::default();
+        if let Some(store) = &store {
+            for record in store.load_callbacks() {
+                match record {
+                    CallbackRecord::Pending(d) => {
+                        inner.next_id = inner.next_id.max(d.id + 1);
+                        inner.pending.push(d);
+                    }
+                    CallbackRecord::Dead(d) => {
+                        inner.next_id = inner.next_id.max(d.id + 1);
+                        inner.dead.push(d);
+                    }
+                    CallbackRecord::Delivered { .. } => {}
+                }
+            }
+            if !inner.pending.is_empty() {
+                info!("Resuming {} undelivered callback(s)", inner.pending.len());
+            }
+        }
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+            notify: Arc::new(Notify::new()),
+            store,
+        }
+    }
+
+    /// Queue `payload` for delivery to `url`.
+    pub async fn enqueue(&self, request_id: u64, url: &str, payload: serde_json::Value) {
+        let mut inner = self.inner.lock().await;
+        let now = store::now_unix();
+        let delivery = Delivery {
+            id: inner.next_id,
+            request_id,
+            url: url.to_string(),
+            payload,
+            attempts: 0,
+            created_unix: now,
+            next_attempt_unix: now,
+            last_error: None,
+        };
+        inner.next_id += 1;
+        self.persist(CallbackRecord::Pending(delivery.clone()));
+        inner.pending.push(delivery);
+        drop(inner);
+        self.notify.notify_one();
+    }
+
+    /// Number of callbacks waiting for a first attempt or a retry.
+    pub async fn pending_len(&self) -> usize {
+        self.inner.lock().await.pending.len()
+    }
+
+    pub async fn dead_letters(&self) -> Vec<Delivery> {
+        self.inner.lock().await.dead.clone()
+    }
+
+    /// Move a dead letter back into the queue with a fresh set of attempts.
+    /// Returns false if there is no dead letter with that id.
+    pub async fn replay(&self, id: u64) -> bool {
+        let mut inner = self.inner.lock().await;
+        let Some(pos) = inner.dead.iter().position(|d| d.id == id) else {
+            return false;
+        };
+        let mut delivery = inner.dead.remove(pos);
+        delivery.attempts = 0;
+        delivery.next_attempt_unix = store::now_unix();
+        self.persist(CallbackRecord::Pending(delivery.clone()));
+        inner.pending.push(delivery);
+        drop(inner);
+        self.notify.notify_one();
+        true
+    }
+
+    /// Take every pending delivery due at `now`.
+    async fn take_due(&self, now: u64) -> Vec<Delivery> {
+        let mut inner = self.inner.lock().await;
+        let (due, waiting) = std::mem::take(&mut inner.pending)
+            .into_iter()
+            .partition(|d| d.next_attempt_unix <= now);
+        inner.pending = waiting;
+        due
+    }
+
+    /// Seconds until the next pending delivery is due, if any is waiting.
+    async fn next_due_in(&self, now: u64) -> Option<u64> {
+        let inner = self.inner.lock().await;
+        inner
+            .pending
+            .iter()
+            .map(|d| d.next_attempt_unix.saturating_sub(now))
+            .min()
+    }
+
+    fn delivered(&self, delivery: &Delivery) {
+        self.persist(CallbackRecord::Delivered { id: delivery.id });
+    }
+
+    /// Schedule a retry, or dead-letter the delivery once it has used all
+    /// its attempts.
+    async fn failed(&self, mut delivery: Delivery, error: String, config: &Config, now: u64) {
+        delivery.attempts += 1;
+        delivery.last_error = Some(error);
+        let mut inner = self.inner.lock().await;
+        if delivery.attempts >= config.callback_max_attempts {
+            warn!(
+                "Callback #{} for request #{} dead-lettered after {} attempt(s)",
+                delivery.id, delivery.request_id, delivery.attempts
+            );
+            METRICS.callbacks.inc("dead");
+            self.persist(CallbackRecord::Dead(delivery.clone()));
+            inner.dead.push(delivery);
+        } else {
+            let delay = backoff(
+                delivery.attempts,
+                config.callback_retry_base_secs,
+                config.callback_retry_max_secs,
+            );
+            delivery.next_attempt_unix = now + delay;
+            self.persist(CallbackRecord::Pending(delivery.clone()));
+            inner.pending.push(delivery);
+        }
+    }
+
+    fn persist(&self, record: CallbackRecord) {
+        if let Some(store) = &self.store {
+            store.append_callback(&record);
+        }
+    }
+}
+
+/// Delay before retry number `attempt` (1-based): `base` doubled for each
+/// earlier retry, at most `max`.
+fn backoff(attempt: u32, base: u64, max: u64) -> u64 {
+    let doublings = attempt.saturating_sub(1).min(32);
+    base.saturating_mul(1 << doublings).min(max)
+}
+
+/// `sha256=<hex>` HMAC-SHA256 of `body` keyed with `secret`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", audit::hex(&mac.finalize().into_bytes()))
+}
+
+/// POST a delivery once. The body is signed exactly as sent.
+async fn attempt(
+    client: &reqwest::Client,
+    delivery: &Delivery,
+    secret: Option<&str>,
+) -> Result<(), String> {
+    let body = delivery.payload.to_string();
+    let mut req = client
+        .post(&delivery.url)
+        .timeout(ATTEMPT_TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(DELIVERY_HEADER, delivery.id.to_string());
+    if let Some(secret) = secret {
+        req = req.header(SIGNATURE_HEADER, sign(secret, body.as_bytes()));
+    }
+    match req.body(body).send().await {
+        Ok(resp) if resp.status().is_success() => {
+            METRICS.callbacks.inc("success");
+            Ok(())
+        }
+        Ok(resp) => {
+            METRICS.callbacks.inc("http_error");
+            Err(format!("HTTP {}", resp.status()))
+        }
+        Err(e) => {
+            METRICS.callbacks.inc("error");
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Start the delivery loop. Due callbacks are sent concurrently; the loop
+/// wakes when one is queued or the next retry comes due.
+pub fn spawn(webhooks: Webhooks, settings: SharedSettings) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        loop {
+            let now = store::now_unix();
+            let settings = settings.load();
+            let secret = settings.config.callback_secret.clone();
+
+            let mut attempts = JoinSet::new();
+            for delivery in webhooks.take_due(now).await {
+                let (client, secret) = (client.clone(), secret.clone());
+                attempts.spawn(async move {
+                    let result = attempt(&client, &delivery, secret.as_deref()).await;
+                    (delivery, result)
+                });
+            }
+            while let Some(joined) = attempts.join_next().await {
+                let Ok((delivery, result)) = joined else {
+                    continue;
+                };
+                match result {
+                    Ok(()) => {
+                        info!(
+                            "Callback #{} for request #{} delivered",
+                            delivery.id, delivery.request_id
+                        );
+                        webhooks.delivered(&delivery);
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Callback #{} for request #{} failed: {e}",
+                            delivery.id, delivery.request_id
+                        );
+                        let now = store::now_unix();
+                        webhooks.failed(delivery, e, &settings.config, now).await;
+                    }
+                }
+            }
+
+            let wait = webhooks
+                .next_due_in(store::now_unix())
+                .await
+                .unwrap_or(60)
+                .max(1);
+            tokio::select! {
+                _ = webhooks.notify.notified() => {}
+                _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_matches_rfc_4231() {
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let delays: Vec<u64> = (1..=6).map(|n| backoff(n, 10, 120)).collect();
+        assert_eq!(delays, [10, 20, 40, 80, 120, 120]);
+        assert_eq!(backoff(200, 10, u64::MAX), 10 << 32);
+    }
+
+    #[tokio::test]
+    async fn exhausted_callbacks_are_dead_lettered_and_replayed() {
+        let webhooks = Webhooks::new(None);
+        let config = Config {
+            callback_max_attempts: 2,
+            ..Config::default()
+        };
+        webhooks
+            .enqueue(7, "http://localhost/hook", serde_json::json!({}))
+            .await;
+
+        let now = store::now_unix();
+        let due = webhooks.take_due(now).await;
+        assert_eq!(due.len(), 1);
+        webhooks
+            .failed(
+                due.into_iter().next().unwrap(),
+                "HTTP 500".into(),
+                &config,
+                now,
+            )
+            .await;
+        assert!(webhooks.take_due(now).await.is_empty());
+        assert_eq!(webhooks.next_due_in(now).await, Some(10));
+
+        let retry = webhooks.take_due(now + 10).await.remove(0);
+        webhooks
+            .failed(retry, "HTTP 500".into(), &config, now + 10)
+            .await;
+        assert_eq!(webhooks.pending_len().await, 0);
+        let dead = webhooks.dead_letters().await;
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(dead[0].last_error.as_deref(), Some("HTTP 500"));
+
+        assert!(webhooks.replay(dead[0].id).await);
+        assert!(!webhooks.replay(dead[0].id).await);
+        assert!(webhooks.dead_letters().await.is_empty());
+        assert_eq!(webhooks.take_due(store::now_unix()).await[0].attempts, 0);
+    }
+}